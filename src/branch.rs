@@ -0,0 +1,140 @@
+use std::sync::Mutex;
+
+use anyhow::{anyhow, Context, Result};
+use gstreamer::prelude::*;
+use log::*;
+
+/// An optional output of the pipeline, plugged on a tee only while it is active.
+///
+/// Unlike a valve, this really closes the output (the preview window, the playback stream...)
+/// while inactive.
+#[derive(Debug)]
+pub struct Branch {
+    pipeline: gstreamer::Pipeline,
+    tee: gstreamer::Element,
+    description: String,
+    /// The tee pad feeding the branch while active.
+    active: Mutex<Option<gstreamer::Pad>>,
+    /// The elements of the branch, kept after detaching it to recognize its late messages.
+    bin: Mutex<Option<gstreamer::Bin>>,
+}
+
+impl Branch {
+    pub fn new(pipeline: &gstreamer::Pipeline, tee: &str, description: String) -> Result<Branch> {
+        let tee = pipeline
+            .by_name(tee)
+            .ok_or_else(|| anyhow!("missing {}", tee))?;
+
+        Ok(Branch {
+            pipeline: pipeline.clone(),
+            tee,
+            description,
+            active: Mutex::new(None),
+            bin: Mutex::new(None),
+        })
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active.lock().unwrap().is_some()
+    }
+
+    pub fn toggle(&self) -> Result<()> {
+        if self.is_active() {
+            self.detach();
+            Ok(())
+        } else {
+            self.attach()
+        }
+    }
+
+    pub fn attach(&self) -> Result<()> {
+        let mut active = self.active.lock().unwrap();
+        if active.is_some() {
+            return Ok(());
+        }
+
+        let bin = gstreamer::parse_bin_from_description(&self.description, true)
+            .with_context(|| format!("failed to create '{}'", self.description))?;
+        self.pipeline.add(&bin)?;
+        let mut tee_pad = None;
+        if let Err(e) = self.plug(&bin, &mut tee_pad) {
+            // don't leave anything behind for the next attempt
+            if let Some(pad) = tee_pad {
+                self.tee.release_request_pad(&pad);
+            }
+            let _ = bin.set_state(gstreamer::State::Null);
+            let _ = self.pipeline.remove(&bin);
+            return Err(e).with_context(|| format!("failed to start '{}'", self.description));
+        }
+
+        *active = tee_pad;
+        *self.bin.lock().unwrap() = Some(bin);
+
+        Ok(())
+    }
+
+    /// Starts the branch and links it to a new tee pad.
+    fn plug(&self, bin: &gstreamer::Bin, tee_pad: &mut Option<gstreamer::Pad>) -> Result<()> {
+        let sink_pad = bin
+            .static_pad("sink")
+            .ok_or_else(|| anyhow!("missing sink pad"))?;
+        // the branch has to be running before data reaches it
+        bin.sync_state_with_parent()?;
+        let pad = tee_pad.insert(
+            self.tee
+                .request_pad_simple("src_%u")
+                .ok_or_else(|| anyhow!("failed to get a tee pad"))?,
+        );
+        pad.link(&sink_pad)?;
+
+        Ok(())
+    }
+
+    pub fn detach(&self) {
+        let tee_pad = match self.active.lock().unwrap().take() {
+            Some(p) => p,
+            None => return,
+        };
+        let bin = match self.bin.lock().unwrap().clone() {
+            Some(b) => b,
+            None => return,
+        };
+
+        // unlink between two buffers, so that the tee never pushes into a half removed branch;
+        // this happens right away if the tee is idle, or after the buffer it is pushing
+        let pipeline = self.pipeline.clone();
+        let tee = self.tee.clone();
+        let description = self.description.clone();
+        tee_pad.add_probe(gstreamer::PadProbeType::IDLE, move |pad, _| {
+            if let Some(peer) = pad.peer() {
+                let _ = pad.unlink(&peer);
+            }
+
+            // the streaming thread of the tee can't stop the branch itself
+            let (pipeline, tee, bin, pad) =
+                (pipeline.clone(), tee.clone(), bin.clone(), pad.clone());
+            let description = description.clone();
+            std::thread::spawn(move || {
+                if let Err(e) = bin.set_state(gstreamer::State::Null) {
+                    warn!("failed to stop '{}': {}", description, e);
+                }
+                if let Err(e) = pipeline.remove(&bin) {
+                    warn!("failed to remove '{}': {}", description, e);
+                }
+                tee.release_request_pad(&pad);
+            });
+
+            gstreamer::PadProbeReturn::Remove
+        });
+    }
+
+    /// Returns true if the object is part of the branch, for example the source of an error.
+    pub fn contains(&self, object: &gstreamer::Object) -> bool {
+        match &*self.bin.lock().unwrap() {
+            Some(bin) => {
+                object == bin.upcast_ref::<gstreamer::Object>() || object.has_as_ancestor(bin)
+            }
+            None => false,
+        }
+    }
+}
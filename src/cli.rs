@@ -6,6 +6,7 @@ use log::LevelFilter;
 #[clap(
     about = "Use android device as webcam with v4l2loopback",
     setting = clap::AppSettings::NoAutoVersion,
+    disable_help_subcommand = true,
     mut_arg("help", |h| h.help_heading("INFO")),
     mut_arg("version", |h| h.help_heading("INFO")),
 )]
@@ -20,11 +21,28 @@ pub struct ProgramOptions {
     /// v4l2loopback video device to use.
     ///
     /// This device must be one expose by the v4l2loopback kernel module. Check the devices under /dev/video* with
-    /// `v4l2-ctl -d /dev/videoX -D` for the correct one.
-    /// [default: /dev/video0]
+    /// `v4l2-ctl -d /dev/videoX -D` for the correct one. With 'auto', the first v4l2loopback device not already
+    /// in use is picked (optionally with a label matching the 'device_label' configuration value).
+    /// [default: auto]
     #[clap(long, short)]
     pub device: Option<String>,
 
+    /// Video output to use.
+    ///
+    /// 'v4l2' writes the video to a v4l2loopback device, 'pipewire' exposes it as a PipeWire video
+    /// source (which doesn't need the v4l2loopback kernel module), and 'none' disables the local
+    /// video output (for use with --rtsp).
+    /// Defaults to v4l2.
+    #[clap(long, short, possible_values(&["v4l2", "pipewire", "none"]), value_name = "OUTPUT")]
+    pub output: Option<String>,
+
+    /// Create a new v4l2loopback device, and remove it on exit.
+    ///
+    /// The device is labeled "dcamctl (<device model>)" by default, or with the 'device_label'
+    /// configuration value. This needs v4l2loopback 0.12.6+ and write access to /dev/v4l2loopback.
+    #[clap(long, help_heading = "FLAGS")]
+    pub create_device: bool,
+
     /// Output resolution to use.
     ///
     /// The video feed will be resized to this value if needed.
@@ -45,16 +63,85 @@ pub struct ProgramOptions {
     #[clap(long, short, help_heading = "FLAGS")]
     pub no_audio: bool,
 
+    /// Disable video support.
+    ///
+    /// Only forward the audio of the device, to use it as a microphone. The v4l2loopback kernel
+    /// module isn't needed in this mode.
+    #[clap(long, help_heading = "FLAGS")]
+    pub no_video: bool,
+
     /// Disable echo canceling.
     #[clap(long, short = 'C', help_heading = "FLAGS")]
     pub no_echo_cancel: bool,
 
+    /// Whether to make the virtual microphone the default audio input.
+    ///
+    /// With 'keep', the virtual microphone (and the echo cancelled speaker) are created but the
+    /// default audio devices are left untouched, and must be selected manually in applications.
+    /// Defaults to override.
+    #[clap(long, possible_values(&["override", "keep"]), value_name = "MODE")]
+    pub audio_defaults: Option<String>,
+
     /// Flip method used to mirror the video.
     ///
     /// Defaults to none.
     #[clap(long, short, possible_values(&["horizontal", "vertical", "none"]), value_name = "METHOD")]
     pub flip: Option<String>,
 
+    /// Output the video in grayscale.
+    ///
+    /// This can also be toggled from the terminal with the 'g' key.
+    #[clap(long, short, help_heading = "FLAGS")]
+    pub grayscale: bool,
+
+    /// Stop streaming from the device while no application uses the webcam or the microphone.
+    ///
+    /// The video and audio are resumed as soon as an application opens the v4l2loopback device or
+    /// records from the virtual microphone.
+    #[clap(long, help_heading = "FLAGS")]
+    pub pause_when_idle: bool,
+
+    /// Show a local preview of the video in a window.
+    ///
+    /// The preview can be hidden and shown again from the terminal with the 'p' key.
+    #[clap(long, help_heading = "FLAGS")]
+    pub preview: bool,
+
+    /// Latency profile of the video and audio streams.
+    ///
+    /// With 'low', late frames and audio samples are dropped instead of being buffered.
+    /// Defaults to normal.
+    #[clap(long, short, possible_values(&["normal", "low"]), value_name = "PROFILE")]
+    pub latency: Option<String>,
+
+    /// Serve the video and audio over RTSP.
+    ///
+    /// The address, port and mount point of the stream can be changed in the 'rtsp' configuration
+    /// section. By default, it is available on rtsp://127.0.0.1:8554/dcamctl.
+    #[clap(long, help_heading = "FLAGS")]
+    pub rtsp: bool,
+
+    /// Serve the video as MJPEG over HTTP.
+    ///
+    /// The stream is available on /stream.mjpg, and the latest frame on /snapshot.jpg. The address
+    /// and port can be changed in the 'http' configuration section. By default, it is available on
+    /// http://127.0.0.1:8090/stream.mjpg.
+    #[clap(long, help_heading = "FLAGS")]
+    pub http: bool,
+
+    /// Use a test video and audio source instead of an android device.
+    ///
+    /// The test pattern can be changed with the 'test_pattern' and 'test_wave' configuration values.
+    #[clap(long, help_heading = "FLAGS")]
+    pub test_source: bool,
+
+    /// Push the video and audio to a streaming server.
+    ///
+    /// Supports SRT ('srt://host:port') and RTMP ('rtmp://host/app/key') URLs. The video is encoded
+    /// with x264, the audio with Opus for SRT and AAC for RTMP.
+    #[clap(long, value_name = "URL")]
+    pub push: Option<String>,
+
     /// Connect to android device with the given serial.
     #[clap(long, short, value_name = "ANDROID_SERIAL")]
     pub serial: Option<String>,
@@ -79,6 +166,25 @@ pub struct ProgramOptions {
         help_heading = "FLAGS"
     )]
     quiet: i8,
+
+    #[clap(subcommand)]
+    pub command: Option<ProgramCommand>,
+}
+
+#[derive(clap::Subcommand, Debug)]
+pub enum ProgramCommand {
+    /// Restore the audio and adb settings left behind by a dcamctl run that didn't exit cleanly.
+    ///
+    /// This sets the default audio devices back, unloads the audio modules and removes the adb
+    /// port forwarding.
+    #[clap(version)]
+    Cleanup,
+    /// List the audio outputs and inputs.
+    ///
+    /// The names (or part of the descriptions) of the outputs can be used for the
+    /// `echo_cancel.sink_master` configuration value.
+    #[clap(version)]
+    AudioDevices,
 }
 
 impl ProgramOptions {
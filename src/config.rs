@@ -7,15 +7,125 @@ use serde::Deserialize;
 pub struct ProgramConfig {
     pub port: u16,
     pub device: PathBuf,
+    pub device_label: Option<String>,
+    pub create_device: bool,
+    pub exclusive_caps: bool,
+    pub pause_when_idle: bool,
     #[serde(with = "resolution")]
     pub resolution: Option<Resolution>,
     pub no_audio: bool,
+    pub no_video: bool,
     pub no_echo_cancel: bool,
+    pub echo_cancel: EchoCancelConfig,
+    pub audio_defaults: AudioDefaults,
+    pub audio: AudioConfig,
     pub flip: Option<String>,
     pub serial: Option<String>,
+    pub color: ColorBalance,
+    pub preview: bool,
+    pub preview_sink: String,
+    pub latency: Latency,
+    pub av_offset_ms: i64,
+    pub output: Output,
+    pub rtsp: RtspConfig,
+    pub http: HttpConfig,
+    pub push: PushConfig,
+    pub test_source: bool,
+    pub test_pattern: String,
+    pub test_wave: String,
+    #[serde(default)]
+    pub video_filters: Vec<String>,
+    #[serde(default)]
+    pub audio_filters: Vec<String>,
 }
 
 #[derive(Debug, Deserialize)]
+pub struct ColorBalance {
+    pub brightness: f64,
+    pub contrast: f64,
+    pub saturation: f64,
+    pub hue: f64,
+    pub grayscale: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Output {
+    V4l2,
+    Pipewire,
+    #[serde(rename = "none")]
+    Disabled,
+}
+
+/// Format of the audio exposed on the virtual microphone.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AudioConfig {
+    pub rate: u32,
+    pub channels: u8,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub monitor_sink: Option<String>,
+}
+
+/// Tuning of the echo cancellation, and of the noise suppression without it.
+#[derive(Debug, Clone, Deserialize)]
+pub struct EchoCancelConfig {
+    pub method: AecMethod,
+    pub noise_suppression: bool,
+    pub analog_gain_control: bool,
+    pub digital_gain_control: bool,
+    pub high_pass_filter: bool,
+    pub extended_filter: bool,
+    pub rnnoise: bool,
+    pub sink_master: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AecMethod {
+    Webrtc,
+    Speex,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RtspConfig {
+    pub enabled: bool,
+    pub address: String,
+    pub port: u16,
+    pub mount: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HttpConfig {
+    pub enabled: bool,
+    pub address: String,
+    pub port: u16,
+    pub quality: u8,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PushConfig {
+    pub url: Option<String>,
+    pub video_bitrate: u32,
+    pub audio_bitrate: u32,
+}
+
+/// Whether the virtual microphone (and echo cancelled speaker) replace the default audio devices.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioDefaults {
+    Override,
+    Keep,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Latency {
+    Normal,
+    Low,
+}
+
+#[derive(Debug, Clone, Deserialize)]
 pub struct Resolution {
     pub height: u16,
     pub width: u16,
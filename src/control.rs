@@ -1,16 +1,26 @@
+use std::fmt::Write as _;
 use std::io::{Stdout, Write};
-
-use crate::cam_info::CamInfo;
-use anyhow::{anyhow, Error, Result};
-use futures::{FutureExt, Stream, StreamExt};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+use std::time::Duration;
+
+use crate::branch::Branch;
+use crate::cam_info::{CamInfo, CurrentValues};
+use crate::level::AudioLevel;
+use crate::push::PushStatus;
+use anyhow::{anyhow, Result};
+use futures::{future::Either, FutureExt, Stream, StreamExt};
 use gstreamer::prelude::ObjectExt;
 use gstreamer_video::VideoOrientationMethod;
 use log::*;
 use termion::{event::Key, input::TermRead};
-use tokio::{signal::unix::SignalKind, sync::oneshot::Sender};
+use tokio::{signal::unix::SignalKind, sync::oneshot::Sender, time::Instant};
 
 enum Command {
     Quit,
+    Tick,
     ZoomIn,
     ZoomOut,
     Nothing,
@@ -21,73 +31,364 @@ enum Command {
     QualityUp,
     QualityDown,
     Flip,
+    BrightnessUp,
+    BrightnessDown,
+    ContrastUp,
+    ContrastDown,
+    SaturationUp,
+    SaturationDown,
+    HueUp,
+    HueDown,
+    Grayscale,
+    Preview,
+    AudioLater,
+    AudioEarlier,
+    Mute,
+    PushToTalk,
+    PushToTalkEnd,
+    GainUp,
+    GainDown,
+    Monitor,
+}
+
+impl Command {
+    /// Whether this command needs the controls of the android device.
+    fn needs_device(&self) -> bool {
+        use Command::*;
+        matches!(
+            self,
+            ZoomIn | ZoomOut | PanLeft | PanRight | PanUp | PanDown | QualityUp | QualityDown
+        )
+    }
+
+    /// Whether this command needs the video part of the pipeline.
+    fn needs_video(&self) -> bool {
+        use Command::*;
+        matches!(
+            self,
+            Flip | BrightnessUp
+                | BrightnessDown
+                | ContrastUp
+                | ContrastDown
+                | SaturationUp
+                | SaturationDown
+                | HueUp
+                | HueDown
+                | Grayscale
+                | Preview
+                | AudioLater
+                | AudioEarlier
+        )
+    }
+}
+
+/// Parts of the running pipeline that can be controlled from the terminal.
+pub struct Controls {
+    pub video: Option<VideoControls>,
+    pub grayscale: Option<f64>,
+    pub preview: Option<Arc<Branch>>,
+    pub push: Option<Arc<PushStatus>>,
+    pub in_use: Option<Arc<AtomicBool>>,
+    pub audio_sink: Option<gstreamer::Element>,
+    pub av_offset_ms: i64,
+    pub audio_volume: Option<gstreamer::Element>,
+    pub audio_level: Option<Arc<AudioLevel>>,
+    pub monitor: Option<Arc<Branch>>,
+}
+
+/// Video elements of the pipeline, missing in audio-only mode.
+#[derive(Debug)]
+pub struct VideoControls {
+    pub flip: gstreamer::Element,
+    pub balance: gstreamer::Element,
+    pub sink: gstreamer::Element,
+}
+
+/// How long push-to-talk stays active after the last key press.
+///
+/// Terminals don't report key releases, so holding the key is detected through key repeats, which
+/// only start after a delay (usually 250 to 660 ms).
+const PUSH_TO_TALK_HOLD: Duration = Duration::from_millis(700);
+
+/// Step used when nudging the audio/video offset from the terminal.
+const AV_OFFSET_STEP_MS: i64 = 10;
+
+/// Delays the audio (for a positive offset) or the video (for a negative one) on the local outputs.
+pub fn set_av_offset(
+    audio_sink: &gstreamer::Element,
+    video_sink: &gstreamer::Element,
+    offset_ms: i64,
+) {
+    let offset_ns = offset_ms * 1_000_000;
+    audio_sink.set_property("ts-offset", offset_ns.max(0));
+    video_sink.set_property("ts-offset", (-offset_ns).max(0));
 }
 
 #[derive(Debug)]
 struct CamControl {
     quit: Sender<()>,
     port: u16,
-    cam_info: CamInfo,
+    cam_info: Option<CamInfo>,
     stdout: Stdout,
-    video_flip: gstreamer::Element,
+    video: Option<VideoControls>,
     flip_method: VideoOrientationMethod,
+    grayscale: Option<f64>,
+    preview: Option<Arc<Branch>>,
+    push: Option<Arc<PushStatus>>,
+    in_use: Option<Arc<AtomicBool>>,
+    audio_sink: Option<gstreamer::Element>,
+    av_offset_ms: i64,
+    audio_volume: Option<gstreamer::Element>,
+    audio_level: Option<Arc<AudioLevel>>,
+    monitor: Option<Arc<Branch>>,
+    talking_until: Option<Instant>,
 }
 
 impl CamControl {
-    async fn new(
-        quit: Sender<()>,
-        port: u16,
-        video_flip: gstreamer::Element,
-    ) -> Result<CamControl, (Error, Sender<()>)> {
-        match (get_cam_info(port, true).await, get_flip_method(&video_flip)) {
-            (Ok(cam_info), Ok(flip_method)) => Ok(CamControl {
-                quit,
-                port,
-                cam_info,
-                stdout: std::io::stdout(),
-                video_flip,
-                flip_method,
-            }),
-            (Err(e), _) => Err((e, quit)),
-            (_, Err(e)) => Err((e, quit)),
+    async fn new(quit: Sender<()>, port: Option<u16>, controls: Controls) -> CamControl {
+        let Controls {
+            video,
+            grayscale,
+            preview,
+            push,
+            in_use,
+            audio_sink,
+            av_offset_ms,
+            audio_volume,
+            audio_level,
+            monitor,
+        } = controls;
+        // without a device (test source, or unreachable controls), only the gstreamer side can
+        // be controlled
+        let cam_info = match port {
+            Some(port) => match get_cam_info(port, true).await {
+                Ok(cam_info) => Some(cam_info),
+                Err(e) => {
+                    debug!("{}", e);
+                    warn!("failed to connect to droidcam controls; disabling device control.");
+                    None
+                }
+            },
+            None => None,
+        };
+        let flip_method = match &video {
+            Some(v) => get_flip_method(&v.flip),
+            None => VideoOrientationMethod::Identity,
+        };
+        CamControl {
+            quit,
+            port: port.unwrap_or_default(),
+            cam_info,
+            stdout: std::io::stdout(),
+            video,
+            flip_method,
+            grayscale,
+            preview,
+            push,
+            in_use,
+            audio_sink,
+            av_offset_ms,
+            audio_volume,
+            audio_level,
+            monitor,
+            talking_until: None,
         }
     }
 
+    /// Fetches the current values of the device controls.
     async fn refresh(&mut self) -> Result<()> {
-        let new = get_cam_info(self.port, false).await?;
+        if let Some(cam_info) = &mut self.cam_info {
+            let new = get_cam_info(self.port, false).await?;
 
-        self.cam_info.curvals = new.curvals;
-
-        self.flip_method = get_flip_method(&self.video_flip)?;
+            cam_info.curvals = new.curvals;
+        }
 
         Ok(())
     }
 
     fn display_status(&mut self) -> Result<()> {
         if log_enabled!(log::Level::Error) {
-            if let Some((zoom_idx, zoom_end)) = self.zoom_index() {
-                let p = (100 * zoom_idx) / zoom_end;
-                let q = self.cam_info.curvals.quality;
-
-                let f = match self.flip_method {
-                    VideoOrientationMethod::Horiz => ", Flip: H",
-                    VideoOrientationMethod::Vert => ", Flip: V",
-                    _ => "         ",
-                };
+            let mut status = String::new();
+
+            if let Some(cam_info) = &self.cam_info {
+                if let Some((zoom_idx, zoom_end)) = self.zoom_index() {
+                    let p = (100 * zoom_idx) / zoom_end;
+                    write!(status, "Zoom: {:2} %, ", p)?;
+                }
+                write!(status, "Quality: {:2} %, ", cam_info.curvals.quality)?;
+            }
 
-                write!(self.stdout, "Zoom: {:2} %, Quality: {:2} %{}\r", p, q, f)?;
-                self.stdout.flush()?;
+            if let Some(video) = &self.video {
+                let b: f64 = video.balance.property("brightness");
+                let c: f64 = video.balance.property("contrast");
+                let h: f64 = video.balance.property("hue");
+                write!(status, "B: {:+.1}, C: {:.1}, ", b, c)?;
+                if self.grayscale.is_some() {
+                    write!(status, "S: gray, ")?;
+                } else {
+                    let s: f64 = video.balance.property("saturation");
+                    write!(status, "S: {:.1}, ", s)?;
+                }
+                write!(status, "H: {:+.1}", h)?;
+            } else {
+                write!(status, "Audio only")?;
             }
+
+            match self.flip_method {
+                VideoOrientationMethod::Horiz => write!(status, ", Flip: H")?,
+                VideoOrientationMethod::Vert => write!(status, ", Flip: V")?,
+                _ => {}
+            };
+            if let Some(preview) = &self.preview {
+                if preview.is_active() {
+                    write!(status, ", Preview")?;
+                }
+            }
+            if let Some(in_use) = &self.in_use {
+                if in_use.load(Ordering::Relaxed) {
+                    write!(status, ", in use")?;
+                } else {
+                    write!(status, ", idle")?;
+                }
+            }
+            if let Some(volume) = &self.audio_volume {
+                let gain: f64 = volume.property("volume");
+                if self.talking_until.is_some() {
+                    write!(status, ", Mic: talking")?;
+                } else if volume.property::<bool>("mute") {
+                    write!(status, ", Mic: muted")?;
+                } else {
+                    write!(status, ", Mic: on")?;
+                }
+                write!(status, " {:.0} %", gain * 100.0)?;
+            }
+            if let Some(level) = &self.audio_level {
+                write!(status, ", Level: {}", level.describe())?;
+            }
+            if let Some(monitor) = &self.monitor {
+                if monitor.is_active() {
+                    write!(status, ", Monitor")?;
+                }
+            }
+            if self.audio_sink.is_some() && self.video.is_some() {
+                write!(status, ", A/V: {:+} ms", self.av_offset_ms)?;
+            }
+            if let Some(push) = &self.push {
+                write!(status, ", Push: {}", push.describe())?;
+            }
+
+            write!(self.stdout, "{}{}\r", termion::clear::CurrentLine, status)?;
+            self.stdout.flush()?;
         }
 
         Ok(())
     }
 
+    fn curvals(&self) -> Result<&CurrentValues> {
+        self.cam_info
+            .as_ref()
+            .map(|c| &c.curvals)
+            .ok_or_else(|| anyhow!("no device controls available"))
+    }
+
+    fn nudge_balance(&self, property: &str, step: f64, min: f64, max: f64) {
+        if let Some(video) = &self.video {
+            let current: f64 = video.balance.property(property);
+            // round to avoid accumulating float errors when stepping back and forth
+            let new = ((current + step) * 100.0).round() / 100.0;
+            video.balance.set_property(property, new.clamp(min, max));
+        }
+    }
+
+    fn nudge_saturation(&mut self, step: f64) {
+        if let Some(video) = &self.video {
+            // changing the saturation gets us out of grayscale mode
+            if let Some(saturation) = self.grayscale.take() {
+                video.balance.set_property("saturation", saturation);
+            }
+        }
+        self.nudge_balance("saturation", step, 0.0, 2.0);
+    }
+
+    fn toggle_grayscale(&mut self) {
+        if let Some(video) = &self.video {
+            if let Some(saturation) = self.grayscale.take() {
+                video.balance.set_property("saturation", saturation);
+            } else {
+                let saturation: f64 = video.balance.property("saturation");
+                self.grayscale = Some(saturation);
+                video.balance.set_property("saturation", 0.0f64);
+            }
+        }
+    }
+
+    fn toggle_preview(&self) {
+        if let Some(preview) = &self.preview {
+            if let Err(e) = preview.toggle() {
+                warn!("failed to toggle preview: {:#}", e);
+            }
+        }
+    }
+
+    fn toggle_monitor(&self) {
+        if let Some(monitor) = &self.monitor {
+            if let Err(e) = monitor.toggle() {
+                warn!("failed to toggle the local monitor: {:#}", e);
+            }
+        }
+    }
+
+    fn nudge_av_offset(&mut self, step: i64) {
+        if let (Some(audio_sink), Some(video)) = (&self.audio_sink, &self.video) {
+            self.av_offset_ms += step;
+            set_av_offset(audio_sink, &video.sink, self.av_offset_ms);
+        }
+    }
+
+    fn toggle_mute(&mut self) {
+        if let Some(volume) = &self.audio_volume {
+            if self.talking_until.take().is_some() {
+                // releasing push-to-talk early: stay unmuted
+                volume.set_property("mute", false);
+                return;
+            }
+            let muted: bool = volume.property("mute");
+            volume.set_property("mute", !muted);
+        }
+    }
+
+    /// Unmutes the microphone for a little while, if it is muted.
+    fn push_to_talk(&mut self) {
+        if let Some(volume) = &self.audio_volume {
+            if self.talking_until.is_some() || volume.property::<bool>("mute") {
+                volume.set_property("mute", false);
+                self.talking_until = Some(Instant::now() + PUSH_TO_TALK_HOLD);
+            }
+        }
+    }
+
+    fn end_push_to_talk(&mut self) {
+        if let Some(volume) = &self.audio_volume {
+            if self.talking_until.take().is_some() {
+                volume.set_property("mute", true);
+            }
+        }
+    }
+
+    fn nudge_gain(&self, step: f64) {
+        if let Some(volume) = &self.audio_volume {
+            let current: f64 = volume.property("volume");
+            let new = ((current + step) * 100.0).round() / 100.0;
+            volume.set_property("volume", new.clamp(0.0, 4.0));
+        }
+    }
+
     fn zoom_index(&self) -> Option<(usize, usize)> {
-        let h = &self.cam_info.avail.as_ref()?.zoom;
+        let cam_info = self.cam_info.as_ref()?;
+        let h = &cam_info.avail.as_ref()?.zoom;
         let idx = h.iter().position(|e| {
             if let Ok(i) = e.parse::<u16>() {
-                i == self.cam_info.curvals.zoom
+                i == cam_info.curvals.zoom
             } else {
                 false
             }
@@ -121,10 +422,8 @@ impl CamControl {
     }
 }
 
-fn get_flip_method(video_flip: &gstreamer::Element) -> Result<VideoOrientationMethod> {
-    let m: VideoOrientationMethod = video_flip.property("video-direction");
-
-    Ok(m)
+fn get_flip_method(video_flip: &gstreamer::Element) -> VideoOrientationMethod {
+    video_flip.property("video-direction")
 }
 
 pub async fn get_cam_info(port: u16, init: bool) -> Result<CamInfo> {
@@ -142,26 +441,14 @@ pub async fn get_cam_info(port: u16, init: bool) -> Result<CamInfo> {
     Ok(c)
 }
 
-pub async fn process_commands(port: u16, video_flip: gstreamer::Element) -> Result<()> {
+pub async fn process_commands(port: Option<u16>, controls: Controls) -> Result<()> {
     let (tx, rx) = tokio::sync::oneshot::channel();
 
     let j = tokio::task::spawn(async move {
-        match CamControl::new(tx, port, video_flip).await {
-            Ok(c) => {
-                match process_commands_inner(c).await {
-                    Ok(_) => {}
-                    Err(e) => error!("{}", e),
-                };
-            }
-            Err((e, tx)) => {
-                debug!("{}", e);
-                warn!("failed to connect to droidcam controls; disabling device control.");
-                match process_commands_fallback(tx).await {
-                    Ok(_) => {}
-                    Err(e) => error!("{}", e),
-                };
-            }
-        };
+        let control = CamControl::new(tx, port, controls).await;
+        if let Err(e) = process_commands_inner(control).await {
+            error!("{}", e);
+        }
     });
 
     futures::future::select(rx, j).await;
@@ -170,16 +457,83 @@ pub async fn process_commands(port: u16, video_flip: gstreamer::Element) -> Resu
 }
 
 async fn process_commands_inner(control: CamControl) -> Result<()> {
-    let mut cmds = input_commands().boxed();
+    // the status line is also redrawn periodically, for the values that change on their own
+    let ticks = async_stream::stream! {
+        let mut interval = tokio::time::interval(Duration::from_secs(1));
+        loop {
+            interval.tick().await;
+            yield Command::Tick;
+        }
+    };
+    let mut cmds = futures::stream::select(input_commands(), ticks).boxed();
     let mut control = control;
 
-    writeln!(
-        control.stdout,
-        "Press 'q': quit, 'z'/'Z': zoom, 't'/'T': quality, 'f': flip, arrows: pan.\r"
-    )?;
+    if control.video.is_none() {
+        writeln!(control.stdout, "Press 'q': quit.\r")?;
+    } else if control.cam_info.is_some() {
+        writeln!(
+            control.stdout,
+            "Press 'q': quit, 'z'/'Z': zoom, 't'/'T': quality, 'f': flip, arrows: pan.\r"
+        )?;
+    } else {
+        writeln!(control.stdout, "Press 'q': quit, 'f': flip.\r")?;
+    }
+    if control.video.is_some() {
+        writeln!(
+            control.stdout,
+            "      'b'/'B': brightness, 'c'/'C': contrast, 's'/'S': saturation, 'h'/'H': hue, 'g': grayscale.\r"
+        )?;
+    }
+    if control.preview.is_some() {
+        writeln!(control.stdout, "      'p': show/hide preview.\r")?;
+    }
+    if control.audio_volume.is_some() {
+        writeln!(
+            control.stdout,
+            "      'm': mute/unmute microphone, hold space: push-to-talk, 'v'/'V': microphone gain.\r"
+        )?;
+    }
+    if control.monitor.is_some() {
+        writeln!(
+            control.stdout,
+            "      'l': play/stop the device audio on the local speakers.\r"
+        )?;
+    }
+    if control.audio_sink.is_some() && control.video.is_some() {
+        writeln!(
+            control.stdout,
+            "      'a'/'A': delay audio/video by {} ms.\r",
+            AV_OFFSET_STEP_MS
+        )?;
+    }
     control.display_status()?;
-    while let Some(cmd) = cmds.next().await {
+    loop {
+        let cmd = match control.talking_until {
+            Some(deadline) => {
+                let release = Box::pin(tokio::time::sleep_until(deadline));
+                match futures::future::select(cmds.next(), release).await {
+                    Either::Left((cmd, _)) => cmd,
+                    Either::Right(_) => Some(Command::PushToTalkEnd),
+                }
+            }
+            None => cmds.next().await,
+        };
+        let cmd = match cmd {
+            Some(cmd) => cmd,
+            None => break,
+        };
+        if cmd.needs_device() && control.cam_info.is_none() {
+            continue;
+        }
+        if cmd.needs_video() && control.video.is_none() {
+            continue;
+        }
+
         match cmd {
+            Command::Tick => {
+                control.display_status()?;
+                continue;
+            }
             Command::Quit => {
                 if log_enabled!(log::Level::Error) {
                     write!(control.stdout, "{}", termion::clear::CurrentLine)?;
@@ -209,7 +563,7 @@ async fn process_commands_inner(control: CamControl) -> Result<()> {
                 .await?;
             }
             Command::PanLeft => {
-                let new_x = &control.cam_info.curvals.crop_x.max(1) - 1;
+                let new_x = &control.curvals()?.crop_x.max(1) - 1;
                 reqwest::get(format!(
                     "http://127.0.0.1:{}/settings/crop_x?set={}",
                     control.port, new_x
@@ -217,7 +571,7 @@ async fn process_commands_inner(control: CamControl) -> Result<()> {
                 .await?;
             }
             Command::PanRight => {
-                let new_x = &control.cam_info.curvals.crop_x + 1;
+                let new_x = &control.curvals()?.crop_x + 1;
                 reqwest::get(format!(
                     "http://127.0.0.1:{}/settings/crop_x?set={}",
                     control.port, new_x
@@ -225,7 +579,7 @@ async fn process_commands_inner(control: CamControl) -> Result<()> {
                 .await?;
             }
             Command::PanUp => {
-                let new_x = &control.cam_info.curvals.crop_y.max(1) - 1;
+                let new_x = &control.curvals()?.crop_y.max(1) - 1;
                 reqwest::get(format!(
                     "http://127.0.0.1:{}/settings/crop_y?set={}",
                     control.port, new_x
@@ -233,7 +587,7 @@ async fn process_commands_inner(control: CamControl) -> Result<()> {
                 .await?;
             }
             Command::PanDown => {
-                let new_x = &control.cam_info.curvals.crop_y + 1;
+                let new_x = &control.curvals()?.crop_y + 1;
                 reqwest::get(format!(
                     "http://127.0.0.1:{}/settings/crop_y?set={}",
                     control.port, new_x
@@ -241,7 +595,7 @@ async fn process_commands_inner(control: CamControl) -> Result<()> {
                 .await?;
             }
             Command::QualityUp => {
-                let new_q = &control.cam_info.curvals.quality + 1;
+                let new_q = &control.curvals()?.quality + 1;
                 reqwest::get(format!(
                     "http://127.0.0.1:{}/settings/quality?set={}",
                     control.port, new_q
@@ -249,7 +603,7 @@ async fn process_commands_inner(control: CamControl) -> Result<()> {
                 .await?;
             }
             Command::QualityDown => {
-                let new_q = &control.cam_info.curvals.quality - 1;
+                let new_q = &control.curvals()?.quality - 1;
                 reqwest::get(format!(
                     "http://127.0.0.1:{}/settings/quality?set={}",
                     control.port, new_q
@@ -263,31 +617,39 @@ async fn process_commands_inner(control: CamControl) -> Result<()> {
                     Horiz => Vert,
                     _ => Identity,
                 };
-                control.video_flip.set_property("video-direction", new);
+                if let Some(video) = &control.video {
+                    video.flip.set_property("video-direction", new);
+                    control.flip_method = get_flip_method(&video.flip);
+                }
             }
+            Command::BrightnessUp => control.nudge_balance("brightness", 0.1, -1.0, 1.0),
+            Command::BrightnessDown => control.nudge_balance("brightness", -0.1, -1.0, 1.0),
+            Command::ContrastUp => control.nudge_balance("contrast", 0.1, 0.0, 2.0),
+            Command::ContrastDown => control.nudge_balance("contrast", -0.1, 0.0, 2.0),
+            Command::SaturationUp => control.nudge_saturation(0.1),
+            Command::SaturationDown => control.nudge_saturation(-0.1),
+            Command::HueUp => control.nudge_balance("hue", 0.1, -1.0, 1.0),
+            Command::HueDown => control.nudge_balance("hue", -0.1, -1.0, 1.0),
+            Command::Grayscale => control.toggle_grayscale(),
+            Command::Preview => control.toggle_preview(),
+            Command::Monitor => control.toggle_monitor(),
+            Command::AudioLater => control.nudge_av_offset(AV_OFFSET_STEP_MS),
+            Command::AudioEarlier => control.nudge_av_offset(-AV_OFFSET_STEP_MS),
+            Command::Mute => control.toggle_mute(),
+            Command::PushToTalk => control.push_to_talk(),
+            Command::PushToTalkEnd => control.end_push_to_talk(),
+            Command::GainUp => control.nudge_gain(0.1),
+            Command::GainDown => control.nudge_gain(-0.1),
         }
 
-        control.refresh().await?;
-        control.display_status()?;
-    }
-
-    Ok(())
-}
-
-async fn process_commands_fallback(quit: Sender<()>) -> Result<()> {
-    let mut cmds = input_commands().boxed();
-    let mut stdout = std::io::stdout();
-
-    writeln!(stdout, "Press 'q' to quit.\r")?;
-    while let Some(cmd) = cmds.next().await {
-        if let Command::Quit = cmd {
-            if log_enabled!(log::Level::Error) {
-                write!(stdout, "{}", termion::clear::CurrentLine)?;
-                stdout.flush()?;
+        // only the device values can change behind our back; failing to get them shouldn't end
+        // the session
+        if cmd.needs_device() {
+            if let Err(e) = control.refresh().await {
+                warn!("failed to refresh device controls: {:#}", e);
             }
-            quit.send(()).map_err(|_| anyhow!("broken channel"))?;
-            break;
         }
+        control.display_status()?;
     }
 
     Ok(())
@@ -318,6 +680,23 @@ fn input_commands() -> impl Stream<Item = Command> {
         Key::Char('t') => QualityUp,
         Key::Char('T') => QualityDown,
         Key::Char('f') => Flip,
+        Key::Char('b') => BrightnessUp,
+        Key::Char('B') => BrightnessDown,
+        Key::Char('c') => ContrastUp,
+        Key::Char('C') => ContrastDown,
+        Key::Char('s') => SaturationUp,
+        Key::Char('S') => SaturationDown,
+        Key::Char('h') => HueUp,
+        Key::Char('H') => HueDown,
+        Key::Char('g') => Grayscale,
+        Key::Char('p') => Preview,
+        Key::Char('a') => AudioLater,
+        Key::Char('A') => AudioEarlier,
+        Key::Char('m') => Mute,
+        Key::Char(' ') => PushToTalk,
+        Key::Char('v') => GainUp,
+        Key::Char('V') => GainDown,
+        Key::Char('l') => Monitor,
         Key::Left => PanLeft,
         Key::Right => PanRight,
         Key::Up => PanUp,
@@ -0,0 +1,171 @@
+use std::sync::Arc;
+
+use anyhow::{anyhow, Context, Result};
+use gstreamer::prelude::*;
+use gstreamer_app::{AppSink, AppSinkCallbacks};
+use log::*;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::watch,
+    task::JoinHandle,
+};
+
+use crate::config::HttpConfig;
+
+type Frame = Option<Arc<Vec<u8>>>;
+
+const BOUNDARY: &str = "dcamctlframe";
+
+/// Serves the JPEG frames coming out of the given appsink over HTTP, both as a MJPEG stream
+/// (`/stream.mjpg`) and as single images (`/snapshot.jpg`).
+pub async fn serve(conf: &HttpConfig, sink: gstreamer::Element) -> Result<JoinHandle<()>> {
+    let sink: AppSink = sink
+        .downcast()
+        .map_err(|_| anyhow!("mjpeg sink isn't an appsink"))?;
+
+    // every client just follows the latest frame, slow ones skip frames
+    let (tx, rx) = watch::channel::<Frame>(None);
+    sink.set_callbacks(
+        AppSinkCallbacks::builder()
+            .new_sample(move |sink| {
+                let sample = sink.pull_sample().map_err(|_| gstreamer::FlowError::Eos)?;
+                let buffer = sample.buffer().ok_or(gstreamer::FlowError::Error)?;
+                let map = buffer
+                    .map_readable()
+                    .map_err(|_| gstreamer::FlowError::Error)?;
+                // no receiver left only happens on shutdown
+                let _ = tx.send(Some(Arc::new(map.to_vec())));
+
+                Ok(gstreamer::FlowSuccess::Ok)
+            })
+            .build(),
+    );
+
+    let listener = TcpListener::bind((conf.address.as_str(), conf.port))
+        .await
+        .with_context(|| {
+            format!(
+                "failed to listen for HTTP on {}:{}",
+                conf.address, conf.port
+            )
+        })?;
+    info!(
+        "serving MJPEG stream on http://{}:{}/stream.mjpg",
+        conf.address, conf.port
+    );
+
+    Ok(tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, addr)) => {
+                    debug!("new HTTP client {}", addr);
+                    let rx = rx.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = handle_client(stream, rx).await {
+                            debug!("HTTP client {}: {}", addr, e);
+                        }
+                    });
+                }
+                Err(e) => warn!("failed to accept HTTP client: {}", e),
+            }
+        }
+    }))
+}
+
+async fn handle_client(mut stream: TcpStream, mut rx: watch::Receiver<Frame>) -> Result<()> {
+    let path = read_request_path(&mut stream).await?;
+    trace!("HTTP request for '{}'", path);
+
+    match path.as_str() {
+        "/stream.mjpg" => {
+            stream
+                .write_all(
+                    format!(
+                        "HTTP/1.0 200 OK\r\nCache-Control: no-cache\r\nConnection: close\r\nContent-Type: multipart/x-mixed-replace; boundary={}\r\n\r\n",
+                        BOUNDARY
+                    )
+                    .as_bytes(),
+                )
+                .await?;
+
+            loop {
+                rx.changed().await?;
+                let frame = rx.borrow().clone();
+                if let Some(frame) = frame {
+                    stream
+                        .write_all(
+                            format!(
+                                "--{}\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n",
+                                BOUNDARY,
+                                frame.len()
+                            )
+                            .as_bytes(),
+                        )
+                        .await?;
+                    stream.write_all(&frame).await?;
+                    stream.write_all(b"\r\n").await?;
+                }
+            }
+        }
+        "/snapshot.jpg" => {
+            let frame = rx.borrow().clone();
+            if let Some(frame) = frame {
+                stream
+                    .write_all(
+                        format!(
+                            "HTTP/1.0 200 OK\r\nCache-Control: no-cache\r\nConnection: close\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n",
+                            frame.len()
+                        )
+                        .as_bytes(),
+                    )
+                    .await?;
+                stream.write_all(&frame).await?;
+            } else {
+                write_status(&mut stream, "503 Service Unavailable").await?;
+            }
+        }
+        _ => write_status(&mut stream, "404 Not Found").await?,
+    }
+
+    stream.shutdown().await?;
+
+    Ok(())
+}
+
+async fn write_status(stream: &mut TcpStream, status: &str) -> Result<()> {
+    stream
+        .write_all(
+            format!(
+                "HTTP/1.0 {}\r\nConnection: close\r\nContent-Length: 0\r\n\r\n",
+                status
+            )
+            .as_bytes(),
+        )
+        .await?;
+
+    Ok(())
+}
+
+/// Reads the request headers and returns the requested path, without the query string.
+async fn read_request_path(stream: &mut TcpStream) -> Result<String> {
+    let mut request = Vec::new();
+    let mut buf = [0; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+        if request.len() > 8192 {
+            return Err(anyhow!("request headers too long"));
+        }
+        let n = stream.read(&mut buf).await?;
+        if n == 0 {
+            return Err(anyhow!("connection closed before the end of the request"));
+        }
+        request.extend_from_slice(&buf[..n]);
+    }
+
+    let request = String::from_utf8_lossy(&request);
+    let mut parts = request.split_whitespace();
+    match (parts.next(), parts.next()) {
+        (Some("GET"), Some(target)) => Ok(target.split('?').next().unwrap_or("/").to_owned()),
+        _ => Err(anyhow!("unsupported HTTP request")),
+    }
+}
@@ -0,0 +1,90 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use gstreamer::glib;
+
+/// Below this peak level (in dB), the audio input is considered silent.
+const SILENCE_DB: f64 = -60.0;
+/// How long the audio input has to stay silent before warning about it.
+const SILENCE_WARNING: Duration = Duration::from_secs(10);
+/// Width of the meter shown in the status line, in characters.
+const METER_WIDTH: usize = 10;
+
+/// Audio levels of the device microphone, as measured by the `level` element of the pipeline.
+#[derive(Debug)]
+pub struct AudioLevel {
+    levels: Mutex<Levels>,
+}
+
+#[derive(Debug)]
+struct Levels {
+    peak: f64,
+    rms: f64,
+    last_sound: Instant,
+}
+
+impl Default for AudioLevel {
+    fn default() -> Self {
+        AudioLevel {
+            levels: Mutex::new(Levels {
+                peak: f64::NEG_INFINITY,
+                rms: f64::NEG_INFINITY,
+                // give the device some time to start sending sound
+                last_sound: Instant::now(),
+            }),
+        }
+    }
+}
+
+impl AudioLevel {
+    /// Updates the levels from a message posted by the `level` element.
+    pub fn update(&self, s: &gstreamer::StructureRef) {
+        // the loudest channel is what matters
+        let loudest = |field| {
+            s.get::<glib::ValueArray>(field)
+                .ok()?
+                .iter()
+                .filter_map(|v| v.get::<f64>().ok())
+                .reduce(f64::max)
+        };
+        let (peak, rms) = match (loudest("peak"), loudest("rms")) {
+            (Some(peak), Some(rms)) => (peak, rms),
+            _ => return,
+        };
+
+        let mut levels = self.levels.lock().unwrap();
+        levels.peak = peak;
+        levels.rms = rms;
+        if peak > SILENCE_DB {
+            levels.last_sound = Instant::now();
+        }
+    }
+
+    /// Renders the levels as a meter, with the RMS level as a bar and the peak level as a mark.
+    pub fn describe(&self) -> String {
+        let levels = self.levels.lock().unwrap();
+        let position = |db: f64| {
+            let fraction = ((db - SILENCE_DB) / -SILENCE_DB).clamp(0.0, 1.0);
+            (fraction * METER_WIDTH as f64).round() as usize
+        };
+        let rms = position(levels.rms);
+        let peak = position(levels.peak);
+        let meter: String = (0..METER_WIDTH)
+            .map(|i| {
+                if i < rms {
+                    '#'
+                } else if i + 1 == peak {
+                    '|'
+                } else {
+                    ' '
+                }
+            })
+            .collect();
+
+        if levels.last_sound.elapsed() >= SILENCE_WARNING {
+            format!("[{}] no sound!", meter)
+        } else {
+            format!("[{}] {:3.0} dB", meter, levels.peak.max(SILENCE_DB))
+        }
+    }
+}
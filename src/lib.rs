@@ -1,6 +1,18 @@
-use std::{fmt::Write, io::Stdout, path::Path};
+use std::{
+    fmt::Write,
+    io::Stdout,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
-use crate::config::Resolution;
+use crate::config::{
+    AecMethod, AudioConfig, AudioDefaults, EchoCancelConfig, Latency, Output, ProgramConfig,
+    Resolution,
+};
 use anyhow::{anyhow, bail, Context, Result};
 use futures::{FutureExt, StreamExt};
 use gstreamer::prelude::*;
@@ -8,15 +20,31 @@ use log::*;
 use regex::Regex;
 use termion::raw::{IntoRawMode, RawTerminal};
 
+// declared first so that the other modules can use the macros
+#[macro_use]
+mod macros;
+
+mod branch;
 mod cam_info;
 pub mod cli;
 pub mod config;
 mod control;
-#[macro_use]
-mod macros;
+mod http;
+mod level;
+pub mod loopback;
+mod pulse;
+mod push;
+#[cfg(feature = "rtsp")]
+mod rtsp;
+mod state;
+
+#[cfg(feature = "rtsp")]
+pub use rtsp::RtspServer;
+pub use state::{check_leftovers, cleanup};
 
 pub struct AdbServer {
     port: u16,
+    serial: Option<String>,
 }
 
 impl AdbServer {
@@ -38,22 +66,83 @@ impl AdbServer {
             run_cmd!("adb", "forward", &port_str, &port_str => "could not enable adb tcp forwarding");
             debug!("forwarding adb port {} to 127.0.0.1:{}", port, port);
         }
+        state::update(|s| {
+            s.adb_forward = Some(state::AdbForward {
+                port,
+                serial: serial.map(|s| s.to_owned()),
+            })
+        });
+
+        Ok(AdbServer {
+            port,
+            serial: serial.map(|s| s.to_owned()),
+        })
+    }
+
+    /// Returns the model name of the connected android device, if it can be found.
+    pub fn device_model(&self) -> Option<String> {
+        let mut cmd = std::process::Command::new("adb");
+        if let Some(serial) = &self.serial {
+            cmd.args(&["-s", serial]);
+        }
+        let output = cmd
+            .args(&["shell", "getprop", "ro.product.model"])
+            .output()
+            .map_err(|e| debug!("could not get device model: {}", e))
+            .ok()?;
 
-        Ok(AdbServer { port })
+        let model = String::from_utf8_lossy(&output.stdout).trim().to_owned();
+        if output.status.success() && !model.is_empty() {
+            trace!("device model = {}", model);
+            Some(model)
+        } else {
+            None
+        }
     }
 }
 
 impl Drop for AdbServer {
     fn drop(&mut self) {
-        run_cmd!("adb", "forward", "--remove", &format!("tcp:{}", self.port) => "could not remove adb tcp forwarding", |s| {
+        remove_adb_forward(self.port, self.serial.as_deref());
+        state::update(|s| s.adb_forward = None);
+    }
+}
+
+fn remove_adb_forward(port: u16, serial: Option<&str>) {
+    let port_str = format!("tcp:{}", port);
+    if let Some(serial) = serial {
+        run_cmd!("adb", "-s", serial, "forward", "--remove", &port_str => "could not remove adb tcp forwarding", |s| {
+            warn!("could not remove adb tcp forwarding (got {})", s)
+        });
+    } else {
+        run_cmd!("adb", "forward", "--remove", &port_str => "could not remove adb tcp forwarding", |s| {
             warn!("could not remove adb tcp forwarding (got {})", s)
         });
     }
 }
 
+/// Name of the inter* channel carrying the final video to the RTSP server.
+const RTSP_VIDEO_CHANNEL: &str = "dcamctl-rtsp-video";
+/// Name of the inter* channel carrying the final audio to the RTSP server.
+const RTSP_AUDIO_CHANNEL: &str = "dcamctl-rtsp-audio";
+/// Name of the inter* channel carrying the final video to the push output.
+const PUSH_VIDEO_CHANNEL: &str = "dcamctl-push-video";
+/// Name of the inter* channel carrying the final audio to the push output.
+const PUSH_AUDIO_CHANNEL: &str = "dcamctl-push-audio";
+
 pub struct Dcam {
-    port: u16,
+    port: Option<u16>,
     pipeline: gstreamer::Pipeline,
+    grayscale: Option<f64>,
+    av_offset_ms: i64,
+    http_server: Option<tokio::task::JoinHandle<()>>,
+    push: Option<push::Push>,
+    preview: Option<Arc<branch::Branch>>,
+    monitor: Option<Arc<branch::Branch>>,
+    audio_level: Option<Arc<level::AudioLevel>>,
+    watched_device: Option<PathBuf>,
+    /// The virtual microphone, which keeps the device streaming while in use.
+    watched_source: Option<String>,
     _audio: Option<AudioSupport>,
     _stdout: RawTerminal<Stdout>,
 }
@@ -61,40 +150,19 @@ pub struct Dcam {
 impl Dcam {
     pub async fn setup(
         audio: Option<AudioSupport>,
-        device: &Path,
-        resolution: Option<Resolution>,
-        port: u16,
-        flip: Option<String>,
+        conf: &ProgramConfig,
+        model: Option<&str>,
     ) -> Result<Dcam> {
         let mut _stdout = std::io::stdout().into_raw_mode()?;
+        let port = conf.port;
 
-        let resolution = match resolution {
-            Some(r) => r,
-            None => match control::get_cam_info(port, false).await {
-                Ok(cam_info) => {
-                    debug!(
-                        "autodetecting default resolution of {}",
-                        cam_info.curvals.video_size
-                    );
-                    cam_info.curvals.video_size
-                }
-                Err(e) => {
-                    debug!("{}", e);
-                    warn!("failed to autodetect device resolution; using 640x480");
-                    Resolution {
-                        height: 480,
-                        width: 640,
-                    }
-                }
-            },
+        let node = NodeNames::new(conf, model);
+        let device_str = match conf.output {
+            Output::V4l2 => conf.device.to_string_lossy(),
+            Output::Pipewire => format!("PipeWire node '{}'", node.name).into(),
+            Output::Disabled => "none".into(),
         };
-
-        let device_str = device.to_string_lossy();
-        let caps = format!(
-            "video/x-raw,format=YUY2,width={},height={}",
-            resolution.width, resolution.height
-        );
-        let method = match flip.as_deref() {
+        let method = match conf.flip.as_deref() {
             Some("horizontal") => "horizontal-flip",
             Some("vertical") => "vertical-flip",
             Some("none") | None => "none",
@@ -104,25 +172,288 @@ impl Dcam {
             }
         };
 
+        // in grayscale mode, the configured saturation is kept aside so that it can be
+        // restored when toggling it off
+        let color = &conf.color;
+        let (saturation, grayscale) = if color.grayscale {
+            (0.0, Some(color.saturation))
+        } else {
+            (color.saturation, None)
+        };
+        let balance = format!(
+            "videobalance name=balance_elem brightness={} contrast={} saturation={} hue={}",
+            color.brightness, color.contrast, saturation, color.hue
+        );
+
+        let preview_sink = (conf.preview && !conf.no_video).then(|| conf.preview_sink.as_str());
+
+        // in low latency mode, queues only keep the most recent buffers and sinks
+        // drop anything that arrives late, instead of buffering it
+        let (audio_src, audio_queue, audio_sink, video_src, video_queue, video_sink) =
+            match conf.latency {
+                Latency::Normal => ("", "queue", "sync=true", "", "queue", "sync=true"),
+                Latency::Low => (
+                    "blocksize=1024 keep-alive=true",
+                    "queue leaky=downstream max-size-buffers=4 max-size-bytes=0 max-size-time=0",
+                    "sync=true buffer-time=50000 latency-time=10000",
+                    "keep-alive=true",
+                    "queue leaky=downstream max-size-buffers=1 max-size-bytes=0 max-size-time=0",
+                    "sync=true max-lateness=20000000 qos=true",
+                ),
+            };
+
+        // noise suppression for when echo cancellation (which does it on its own) is disabled
+        let rnnoise = match &audio {
+            Some(a) if conf.echo_cancel.rnnoise && !a.echo_cancel_enabled() => {
+                Some(rnnoise_element(conf.audio.channels)?)
+            }
+            _ => None,
+        };
+
         let mut pipeline_desc = String::new();
-        if audio.is_some() {
-            write!(pipeline_desc, "souphttpsrc location=http://127.0.0.1:{}/audio.wav do-timestamp=true is-live=true ! audio/x-raw,format=S16LE,layout=interleaved,rate=44100,channels=1 ! queue ! pulsesink device=dcamctl_webcam sync=true ", port)?;
+        if let Some(audio) = &audio {
+            let audio_sink_name = &audio.names.sink;
+            if conf.test_source {
+                // the test source can produce the configured format directly
+                write!(
+                    pipeline_desc,
+                    "audiotestsrc is-live=true wave={} ! audio/x-raw,format=S16LE,layout=interleaved,rate={},channels={} ",
+                    conf.test_wave, conf.audio.rate, conf.audio.channels
+                )?;
+            } else {
+                // IP Webcam's /audio.wav takes no format parameters: the app always streams 16-bit
+                // 44.1kHz mono PCM, which is then resampled to the configured format below
+                write!(pipeline_desc, "souphttpsrc name=audio_src location=http://127.0.0.1:{}/audio.wav do-timestamp=true is-live=true {} ! audio/x-raw,format=S16LE,layout=interleaved,rate=44100,channels=1 ", port, audio_src)?;
+            }
+            if let Some(rnnoise) = rnnoise {
+                // RNNoise only works on 48kHz audio
+                write!(pipeline_desc, "! audioconvert ! audioresample ! audio/x-raw,format=F32LE,rate=48000,channels={} ! {} ", conf.audio.channels, rnnoise)?;
+            }
+            write!(pipeline_desc, "! audioconvert ! audioresample ! audio/x-raw,format=S16LE,rate={},channels={} ! {} name=audio_queue ! level name=audio_level interval=1000000000 ! volume name=audio_volume ", conf.audio.rate, conf.audio.channels, audio_queue)?;
+            // additional outputs for the audio, next to the virtual microphone (the local monitor
+            // is plugged on the tee later, only while toggled on)
+            let mut audio_branches = Vec::new();
+            if conf.rtsp.enabled {
+                audio_branches.push(format!(
+                    "queue leaky=downstream ! interaudiosink channel={}",
+                    RTSP_AUDIO_CHANNEL
+                ));
+            }
+            if conf.push.url.is_some() {
+                audio_branches.push(format!(
+                    "queue leaky=downstream ! interaudiosink channel={}",
+                    PUSH_AUDIO_CHANNEL
+                ));
+            }
+
+            write!(
+                pipeline_desc,
+                "tee name=audio_tee ! queue ! pulsesink name=audio_sink device={} {} ",
+                audio_sink_name, audio_sink
+            )?;
+            for branch in audio_branches {
+                write!(pipeline_desc, "audio_tee. ! {} ", branch)?;
+            }
+        }
+        if !conf.no_video {
+            let resolution = detect_resolution(conf).await;
+            info!(
+                "set up video input '{}' with resolution {}",
+                device_str, resolution
+            );
+            let caps = format!(
+                "video/x-raw,format=YUY2,width={},height={}",
+                resolution.width, resolution.height
+            );
+            if conf.test_source {
+                write!(
+                    pipeline_desc,
+                    "videotestsrc is-live=true pattern={} ",
+                    conf.test_pattern
+                )?;
+            } else {
+                // the multipart stream must be kept whole, so the (possibly leaky) video queue only
+                // comes after decoding, where it drops whole frames
+                write!(pipeline_desc, "souphttpsrc name=video_src location=http://127.0.0.1:{}/videofeed do-timestamp=true is-live=true {} ! queue ! multipartdemux ! decodebin ", port, video_src)?;
+            }
+            write!(pipeline_desc, "! {} ! videoflip name=flip_elem method=\"{}\" ! {} videoconvert name=video_convert ! videoscale ! {} ! ", video_queue, method, balance, caps)?;
+            let video_out = match conf.output {
+                Output::V4l2 => format!(
+                    "v4l2sink name=video_out device={} {}",
+                    conf.device.to_string_lossy(),
+                    video_sink
+                ),
+                Output::Pipewire => format!(
+                    "pipewiresink name=video_out mode=provide client-name={} {}",
+                    node.name, video_sink
+                ),
+                Output::Disabled => format!("fakesink name=video_out {}", video_sink),
+            };
+
+            // additional outputs for the final video, next to the main one
+            // (the preview is plugged on the tee later, only while shown)
+            let mut video_branches = Vec::new();
+            if conf.rtsp.enabled {
+                video_branches.push(format!(
+                    "queue leaky=downstream max-size-buffers=1 ! intervideosink channel={}",
+                    RTSP_VIDEO_CHANNEL
+                ));
+            }
+            if conf.push.url.is_some() {
+                video_branches.push(format!(
+                    "queue leaky=downstream max-size-buffers=1 ! intervideosink channel={}",
+                    PUSH_VIDEO_CHANNEL
+                ));
+            }
+            if conf.http.enabled {
+                video_branches.push(format!("queue leaky=downstream max-size-buffers=1 ! videoconvert ! jpegenc quality={} ! appsink name=mjpeg_sink sync=false max-buffers=1 drop=true", conf.http.quality));
+            }
+
+            if video_branches.is_empty() && preview_sink.is_none() {
+                write!(pipeline_desc, "{}", video_out)?;
+            } else {
+                write!(
+                    pipeline_desc,
+                    "tee name=out_tee ! {} ! {}",
+                    video_queue, video_out
+                )?;
+                for branch in video_branches {
+                    write!(pipeline_desc, " out_tee. ! {}", branch)?;
+                }
+            }
         }
-        write!(pipeline_desc, "souphttpsrc location=http://127.0.0.1:{}/videofeed do-timestamp=true is-live=true ! queue ! multipartdemux ! decodebin ! videoflip name=flip_elem method=\"{}\" ! videoconvert ! videoscale ! {} ! v4l2sink device={} sync=true", port, method,  caps, device_str)?;
 
-        let pipeline = gstreamer::parse_launch(&pipeline_desc)?
+        let pipeline: gstreamer::Pipeline = gstreamer::parse_launch(&pipeline_desc)?
             .downcast()
             .map_err(|_| anyhow!("broken pipeline"))?;
 
-        info!(
-            "set up video input '{}' with resolution {}",
-            device_str, resolution
-        );
-        show!(Warn, "\r  Video     : {}\r", device_str);
+        if conf.output == Output::Pipewire && !conf.no_video {
+            // exposed as a video source that applications can pick as a camera
+            let props = gstreamer::Structure::builder("props")
+                .field("media.class", "Video/Source")
+                .field("node.name", &node.name)
+                .field("node.description", &node.description)
+                .build();
+            pipeline
+                .by_name("video_out")
+                .ok_or_else(|| anyhow!("missing pipewiresink"))?
+                .set_property("stream-properties", props);
+        }
+
+        let preview = match preview_sink {
+            Some(sink) => {
+                let preview = branch::Branch::new(
+                    &pipeline,
+                    "out_tee",
+                    format!(
+                        "queue leaky=downstream max-size-buffers=1 ! videoconvert ! {} sync=false",
+                        sink
+                    ),
+                )?;
+                preview.attach()?;
+                Some(Arc::new(preview))
+            }
+            None => None,
+        };
+
+        let monitor = match &audio {
+            Some(audio) => Some(Arc::new(branch::Branch::new(
+                &pipeline,
+                "audio_tee",
+                format!(
+                    "queue leaky=downstream ! pulsesink device={} sync=false",
+                    audio.monitor_sink
+                ),
+            )?)),
+            None => None,
+        };
+
+        if !conf.no_video {
+            link_filters(
+                &pipeline,
+                "video",
+                &conf.video_filters,
+                "videoconvert",
+                "balance_elem",
+                "video_convert",
+            )?;
+        }
+        if audio.is_some() {
+            link_filters(
+                &pipeline,
+                "audio",
+                &conf.audio_filters,
+                "audioconvert ! audioresample",
+                "audio_volume",
+                "audio_tee",
+            )?;
+        }
+        if let (Some(audio_sink), Some(video_sink)) = (
+            pipeline.by_name("audio_sink"),
+            pipeline.by_name("video_out"),
+        ) {
+            control::set_av_offset(&audio_sink, &video_sink, conf.av_offset_ms);
+        }
+
+        let http_server = if conf.http.enabled {
+            let sink = pipeline
+                .by_name("mjpeg_sink")
+                .ok_or_else(|| anyhow!("missing mjpeg appsink"))?;
+            Some(http::serve(&conf.http, sink).await?)
+        } else {
+            None
+        };
+
+        let push = match &conf.push.url {
+            Some(url) => Some(push::Push::start(&conf.push, url, audio.is_some())?),
+            None => None,
+        };
+
+        if conf.test_source {
+            let pattern = &conf.test_pattern;
+            show!(Warn, "\r  Source    : test ({})\r", pattern);
+        }
+        if conf.no_video {
+            show!(Warn, "\r  Video     : disabled\r");
+        } else {
+            show!(Warn, "\r  Video     : {}\r", device_str);
+        }
+        if preview_sink.is_some() {
+            show!(Warn, "\r  Preview   : on\r");
+        }
+        if conf.rtsp.enabled {
+            let r = &conf.rtsp;
+            let url = format!("rtsp://{}:{}{}", r.address, r.port, r.mount);
+            show!(Warn, "\r  RTSP      : {}\r", url);
+        }
+        if conf.http.enabled {
+            let url = format!(
+                "http://{}:{}/stream.mjpg",
+                conf.http.address, conf.http.port
+            );
+            show!(Warn, "\r  MJPEG     : {}\r", url);
+        }
+        if let Some(url) = &conf.push.url {
+            show!(Warn, "\r  Push      : {}\r", url);
+        }
 
         Ok(Dcam {
-            port,
+            // the camera controls of the device are useless without video
+            port: (!conf.test_source && !conf.no_video).then(|| port),
             pipeline,
+            grayscale,
+            av_offset_ms: conf.av_offset_ms,
+            http_server,
+            push,
+            preview,
+            monitor,
+            audio_level: audio.is_some().then(Default::default),
+            watched_device: (conf.pause_when_idle
+                && conf.output == Output::V4l2
+                && !conf.test_source
+                && !conf.no_video)
+                .then(|| conf.device.clone()),
+            watched_source: audio.as_ref().map(|a| a.mic_source.clone()),
             _audio: audio,
             _stdout,
         })
@@ -137,13 +468,55 @@ impl Dcam {
             None => bail!("No bus for gstreamer pipeline"),
         };
 
-        let flip = self
-            .pipeline
-            .by_name("flip_elem")
-            .ok_or_else(|| anyhow!("missing videoflip"))?;
+        let video = match self.pipeline.by_name("video_out") {
+            Some(sink) => Some(crate::control::VideoControls {
+                flip: self
+                    .pipeline
+                    .by_name("flip_elem")
+                    .ok_or_else(|| anyhow!("missing videoflip"))?,
+                balance: self
+                    .pipeline
+                    .by_name("balance_elem")
+                    .ok_or_else(|| anyhow!("missing videobalance"))?,
+                sink,
+            }),
+            None => None,
+        };
+
+        let mut controls = crate::control::Controls {
+            video,
+            grayscale: self.grayscale,
+            preview: self.preview.clone(),
+            push: self.push.as_ref().map(|p| p.status()),
+            in_use: None,
+            audio_sink: self.pipeline.by_name("audio_sink"),
+            audio_volume: self.pipeline.by_name("audio_volume"),
+            audio_level: self.audio_level.clone(),
+            monitor: self.monitor.clone(),
+            av_offset_ms: self.av_offset_ms,
+        };
+
+        let mut idle_watcher = None;
+        if let Some(device) = &self.watched_device {
+            let video_src = self
+                .pipeline
+                .by_name("video_src")
+                .ok_or_else(|| anyhow!("missing video source"))?;
+            let sources = std::iter::once(video_src)
+                .chain(self.pipeline.by_name("audio_src"))
+                .collect();
+            let in_use = Arc::new(AtomicBool::new(true));
+            controls.in_use = Some(in_use.clone());
+            idle_watcher = Some(tokio::spawn(watch_consumers(
+                device.clone(),
+                self.watched_source.clone(),
+                sources,
+                in_use,
+            )));
+        }
 
         let stop_signals = crate::control::stop_signals().boxed_local();
-        let quit_command = crate::control::process_commands(self.port, flip).boxed_local();
+        let quit_command = crate::control::process_commands(self.port, controls).boxed_local();
         let stop_run = futures::future::select(stop_signals, quit_command);
         let mut stream = bus.stream().take_until(stop_run);
 
@@ -155,7 +528,34 @@ impl Dcam {
                     warn!("received end-of-stream, quitting");
                     break;
                 }
+                MessageView::Latency(..) => {
+                    // some element changed its latency, so redistribute it and report
+                    // the new value
+                    if let Err(e) = self.pipeline.recalculate_latency() {
+                        debug!("failed to recalculate latency: {}", e);
+                    }
+                    self.report_latency();
+                }
+                MessageView::AsyncDone(..) => self.report_latency(),
+                MessageView::Element(e) => {
+                    if let (Some(level), Some(s)) = (&self.audio_level, e.structure()) {
+                        if s.name() == "level" {
+                            level.update(s);
+                        }
+                    }
+                }
                 MessageView::Error(err) => {
+                    // the optional outputs can fail on their own (closed preview window...)
+                    // without taking the webcam down
+                    let failed_output = err
+                        .src()
+                        .and_then(|src| self.optional_outputs().find(|b| b.contains(&src)));
+                    if let Some(output) = failed_output {
+                        warn!("closing optional output after error: {}", err.error());
+                        debug!("{:?}", err.debug());
+                        output.detach();
+                        continue;
+                    }
                     error!(
                         "Error from {:?}: {} ({:?})",
                         err.src().map(|s| s.path_string()),
@@ -168,16 +568,158 @@ impl Dcam {
             }
         }
 
+        if let Some(w) = idle_watcher {
+            w.abort();
+        }
+
         self.pipeline.set_state(gstreamer::State::Paused)?;
 
         show!("Disconnected.\r");
 
         Ok(())
     }
+
+    /// The outputs that can be turned on and off while running.
+    fn optional_outputs(&self) -> impl Iterator<Item = &Arc<branch::Branch>> {
+        self.preview.iter().chain(self.monitor.iter())
+    }
+
+    fn report_latency(&self) {
+        let mut q = gstreamer::query::Latency::new();
+        if self.pipeline.query(&mut q) {
+            let (live, min, max) = q.result();
+            info!(
+                "pipeline latency: {} ms (max: {}, live: {})",
+                min.mseconds(),
+                max.map(|m| format!("{} ms", m.mseconds()))
+                    .unwrap_or_else(|| "none".to_owned()),
+                live
+            );
+        } else {
+            debug!("latency query failed");
+        }
+    }
+}
+
+/// Stops pulling the video and audio from the device while no application has the video device
+/// open and no application records from the virtual microphone, so that the device doesn't stream
+/// for nothing.
+async fn watch_consumers(
+    device: PathBuf,
+    mic: Option<String>,
+    sources: Vec<gstreamer::Element>,
+    in_use: Arc<AtomicBool>,
+) {
+    let mut interval = tokio::time::interval(Duration::from_secs(2));
+    loop {
+        interval.tick().await;
+
+        let consumers = loopback::count_consumers(&device);
+        // the microphone can be used on its own, in a call with the camera turned off
+        let used = consumers > 0 || mic.as_deref().map(mic_in_use).unwrap_or(false);
+        if used == in_use.load(Ordering::Relaxed) {
+            continue;
+        }
+        in_use.store(used, Ordering::Relaxed);
+
+        if used {
+            debug!(
+                "{} opened by {} other process(es) or microphone in use, resuming streaming",
+                device.display(),
+                consumers
+            );
+        } else {
+            debug!(
+                "{} and microphone not in use, pausing streaming",
+                device.display()
+            );
+        }
+        for src in &sources {
+            let res: Result<()> = if used {
+                src.set_locked_state(false);
+                src.sync_state_with_parent().map_err(Into::into)
+            } else {
+                // keep the source stopped even if the rest of the pipeline changes state
+                src.set_locked_state(true);
+                src.set_state(gstreamer::State::Null)
+                    .map(|_| ())
+                    .map_err(Into::into)
+            };
+            if let Err(e) = res {
+                warn!("failed to pause/resume {}: {}", src.name(), e);
+            }
+        }
+    }
+}
+
+/// Returns true if an application records from the virtual microphone.
+fn mic_in_use(name: &str) -> bool {
+    // a new connection each time, as it can't be kept across await points
+    match pulse::Pulse::connect().and_then(|mut p| p.source_in_use(name)) {
+        Ok(used) => used,
+        Err(e) => {
+            // better keep streaming than cut the microphone
+            debug!("could not check if the microphone is in use: {:#}", e);
+            true
+        }
+    }
+}
+
+/// Inserts user-provided filters (gst-launch fragments) between the two named elements.
+///
+/// Each filter gets the `convert` elements in front of it, so that it can pick the raw format it
+/// supports.
+fn link_filters(
+    pipeline: &gstreamer::Pipeline,
+    kind: &str,
+    filters: &[String],
+    convert: &str,
+    src: &str,
+    sink: &str,
+) -> Result<()> {
+    let mut last = pipeline
+        .by_name(src)
+        .ok_or_else(|| anyhow!("missing {} element", src))?;
+    let sink = pipeline
+        .by_name(sink)
+        .ok_or_else(|| anyhow!("missing {} element", sink))?;
+
+    let mut last_desc = None;
+    for desc in filters {
+        let bin = gstreamer::parse_bin_from_description(&format!("{} ! {}", convert, desc), true)
+            .with_context(|| format!("failed to parse {} filter '{}'", kind, desc))?;
+        pipeline.add(&bin)?;
+        last.link(&bin).with_context(|| match last_desc {
+            Some(l) => format!("failed to link {} filter '{}' to '{}'", kind, l, desc),
+            None => format!("failed to link {} filter '{}' to the input", kind, desc),
+        })?;
+        debug!("added {} filter '{}'", kind, desc);
+
+        last = bin.upcast();
+        last_desc = Some(desc);
+    }
+    if last_desc.is_some() {
+        // back to a format the rest of the pipeline supports
+        let bin = gstreamer::parse_bin_from_description(convert, true)?;
+        pipeline.add(&bin)?;
+        last.link(&bin)?;
+        last = bin.upcast();
+    }
+
+    last.link(&sink).with_context(|| match last_desc {
+        Some(l) => format!("failed to link {} filter '{}' to the output", kind, l),
+        None => format!("failed to link {} pipeline", kind),
+    })?;
+
+    Ok(())
 }
 
 impl Drop for Dcam {
     fn drop(&mut self) {
+        if let Some(server) = &self.http_server {
+            server.abort();
+        }
+
         // Shutdown pipeline
         if let Err(e) = self.pipeline.set_state(gstreamer::State::Null) {
             error!("{}", e);
@@ -187,92 +729,248 @@ impl Drop for Dcam {
 
 #[derive(Debug)]
 pub struct AudioSupport {
+    pulse: pulse::Pulse,
+    defaults: AudioDefaults,
+    format: AudioConfig,
+    tuning: EchoCancelConfig,
+    names: AudioNames,
     default_source: String,
     default_sink: String,
+    /// The speaker wrapped by the echo cancellation.
+    sink_master: String,
+    /// The speaker the local monitor plays the audio from the device on.
+    monitor_sink: String,
+    /// The source applications record from: the virtual microphone.
+    mic_source: String,
     sink_id: u32,
     echo_cancel: EchoCancel,
 }
 
+/// Name and description of the PipeWire video node.
+#[derive(Debug)]
+struct NodeNames {
+    name: String,
+    description: String,
+}
+
+impl NodeNames {
+    /// Uses the device label, or names derived from the device model, so that several instances
+    /// (one per device) don't collide.
+    fn new(conf: &ProgramConfig, model: Option<&str>) -> NodeNames {
+        match (&conf.device_label, model) {
+            (Some(label), _) => NodeNames {
+                name: format!("dcamctl_{}", sanitize_name(label)),
+                description: label.clone(),
+            },
+            (None, Some(model)) => NodeNames {
+                name: format!("dcamctl_{}", sanitize_name(model)),
+                description: format!("{} Virtual Webcam", model),
+            },
+            (None, None) => NodeNames {
+                name: "dcamctl".to_owned(),
+                description: "dcamctl Virtual Webcam".to_owned(),
+            },
+        }
+    }
+}
+
+/// Turns a label into something usable as a device name.
+fn sanitize_name(label: &str) -> String {
+    label
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Names and descriptions of the virtual audio devices.
+#[derive(Debug)]
+struct AudioNames {
+    /// The sink the audio from the device is played to.
+    sink: String,
+    ec_source: String,
+    ec_sink: String,
+    description: String,
+}
+
+impl AudioNames {
+    /// Uses the configured names, or names derived from the device model.
+    fn new(conf: &AudioConfig, model: Option<&str>) -> AudioNames {
+        let sink = match (&conf.name, model) {
+            (Some(name), _) => name.clone(),
+            (None, Some(model)) => format!("dcamctl_{}", sanitize_name(model)),
+            (None, None) => "dcamctl_webcam".to_owned(),
+        };
+        let description = match (&conf.description, model) {
+            (Some(description), _) => description.clone(),
+            (None, Some(model)) => format!("{} Virtual Microphone", model),
+            (None, None) => "Webcam Virtual Microphone".to_owned(),
+        };
+        // descriptions end up quoted in module arguments
+        let description = description.replace(&['\'', '"'][..], "");
+
+        AudioNames {
+            ec_source: format!("{}_ec_src", sink),
+            ec_sink: format!("{}_ec_aout", sink),
+            sink,
+            description,
+        }
+    }
+
+    fn ec_source_description(&self) -> String {
+        format!("{} (EC-cancelled)", self.description)
+    }
+
+    fn ec_sink_description(&self) -> String {
+        format!("Default Audio Out (EC-cancelled with {})", self.description)
+    }
+}
+
 #[derive(Debug)]
 enum EchoCancel {
-    Pulseaudio { cancel_sink_id: u32 },
+    Pulseaudio {
+        cancel_sink_id: u32,
+    },
+    /// libpipewire-module-echo-cancel, running in its own pipewire process.
+    PipeWireNative {
+        process: Option<std::process::Child>,
+        conf_path: Option<PathBuf>,
+    },
     Disabled,
-    // TODO: PipeWireNative
 }
 
 impl AudioSupport {
-    pub fn new(echo_cancel: bool) -> Result<Option<AudioSupport>> {
-        run_cmd!("pactl", "--version" => "unable to find 'pactl' command");
+    /// Sets up the virtual microphone, named after the device model if known.
+    pub fn new(conf: &ProgramConfig, model: Option<&str>) -> Result<Option<AudioSupport>> {
+        let echo_cancel = !conf.no_echo_cancel;
+        if echo_cancel
+            && conf.echo_cancel.analog_gain_control
+            && conf.echo_cancel.digital_gain_control
+        {
+            bail!(
+                "only one of analog and digital gain control can be enabled for echo cancellation"
+            );
+        }
+        let format = &conf.audio;
+        if !(8000..=192000).contains(&format.rate) {
+            bail!(
+                "unsupported audio rate {} Hz: it must be between 8000 and 192000",
+                format.rate
+            );
+        }
+        if !(1..=32).contains(&format.channels) {
+            bail!(
+                "unsupported number of audio channels {}: it must be between 1 and 32",
+                format.channels
+            );
+        }
+        if conf.echo_cancel.rnnoise && format.channels > 2 {
+            bail!("RNNoise only supports mono and stereo audio");
+        }
 
-        let output = get_cmd!("pactl", "info" => "failed to get pulseaudio info");
-        let out = String::from_utf8_lossy(&output.stdout);
+        let mut pulse = pulse::Pulse::connect()?;
+        let info = pulse
+            .server_info()
+            .context("failed to get pulseaudio info")?;
 
         let re = Regex::new(r"PipeWire ([^[[:space:]]\)]+)?").unwrap();
-        let mut default_sink = String::new();
-        let mut default_source = String::new();
         let mut found_echo_cancel = None;
-        for l in out.lines() {
-            let mut l = l.split(": ");
-            match l.next() {
-                Some("Server Name") => {
-                    if let Some(name) = l.next() {
-                        if let Some(c) = re.captures(name) {
-                            if let Ok(v) = lenient_semver::parse(&c[1]) {
-                                debug!("using pipewire backend");
-                                let acancel_version = lenient_semver::parse("0.3.30")?;
-                                if v < acancel_version {
-                                    debug!(
-                                        "pirewire {} < {}: echo cancellation unsupported",
-                                        v, acancel_version
-                                    );
-                                    found_echo_cancel = Some(false);
-                                } else {
-                                    debug!(
-                                        "pirewire {} >= {}: echo cancellation supported",
-                                        v, acancel_version
-                                    );
-                                    found_echo_cancel = Some(true);
-                                }
-                                continue;
-                            }
-                        }
-
-                        debug!("using pulseaudio backend");
-                        found_echo_cancel = Some(true);
-                    }
-                }
-                Some("Default Sink") => {
-                    if let Some(sink) = l.next() {
-                        default_sink.push_str(sink);
-                    }
+        let mut native_echo_cancel = false;
+        if !info.name.is_empty() {
+            if let Some(v) = re
+                .captures(&info.name)
+                .and_then(|c| lenient_semver::parse(&c[1]).ok())
+            {
+                debug!("using pipewire backend");
+                // the configuration written for the native module (aec.args, target.object...)
+                // isn't understood by older versions
+                let native_version = lenient_semver::parse("0.3.66")?;
+                native_echo_cancel = v >= native_version;
+                if !native_echo_cancel {
+                    debug!(
+                        "pipewire {} < {}: native echo cancellation unsupported",
+                        v, native_version
+                    );
                 }
-                Some("Default Source") => {
-                    if let Some(source) = l.next() {
-                        default_source.push_str(source);
-                    }
+                let acancel_version = lenient_semver::parse("0.3.30")?;
+                if v < acancel_version {
+                    debug!(
+                        "pirewire {} < {}: echo cancellation unsupported",
+                        v, acancel_version
+                    );
+                    found_echo_cancel = Some(false);
+                } else {
+                    debug!(
+                        "pirewire {} >= {}: echo cancellation supported",
+                        v, acancel_version
+                    );
+                    found_echo_cancel = Some(true);
                 }
-                _ => {}
+            } else {
+                debug!("using pulseaudio backend");
+                found_echo_cancel = Some(true);
             }
         }
 
+        let default_sink = info.default_sink;
+        let default_source = info.default_source;
         trace!("default_sink = {}", default_sink);
         trace!("default_source = {}", default_source);
         trace!("echo_cancel = {:?}", found_echo_cancel);
 
         let echo_cancel_backend = if let Some(found) = found_echo_cancel {
-            if echo_cancel && found {
-                EchoCancel::Pulseaudio { cancel_sink_id: 0 }
-            } else {
+            if !echo_cancel || !found {
                 EchoCancel::Disabled
+            } else if native_echo_cancel
+                && conf.echo_cancel.method == AecMethod::Webrtc
+                && has_pipewire_command()
+            {
+                debug!("using native pipewire echo cancellation");
+                EchoCancel::PipeWireNative {
+                    process: None,
+                    conf_path: None,
+                }
+            } else {
+                EchoCancel::Pulseaudio { cancel_sink_id: 0 }
             }
         } else {
             debug!("could not find supported audio backend");
             return Ok(None);
         };
 
+        // checked before loading anything, so that a typo doesn't leave modules behind
+        if conf.echo_cancel.rnnoise && matches!(echo_cancel_backend, EchoCancel::Disabled) {
+            rnnoise_element(format.channels)?;
+        }
+        let sink_master = match &conf.echo_cancel.sink_master {
+            Some(wanted) if !matches!(echo_cancel_backend, EchoCancel::Disabled) => {
+                find_sink(&mut pulse, wanted)?
+            }
+            _ => default_sink.clone(),
+        };
+        trace!("sink_master = {}", sink_master);
+        let monitor_sink = match &conf.audio.monitor_sink {
+            Some(wanted) => find_sink(&mut pulse, wanted)?,
+            None => sink_master.clone(),
+        };
+        trace!("monitor_sink = {}", monitor_sink);
+
         let mut audio_support = AudioSupport {
+            pulse,
+            defaults: conf.audio_defaults,
+            format: conf.audio.clone(),
+            tuning: conf.echo_cancel.clone(),
+            names: AudioNames::new(&conf.audio, model),
             default_sink,
             default_source,
+            sink_master,
+            monitor_sink,
+            mic_source: String::new(),
             sink_id: 0,
             echo_cancel: echo_cancel_backend,
         };
@@ -282,50 +980,94 @@ impl AudioSupport {
         Ok(Some(audio_support))
     }
 
+    fn echo_cancel_enabled(&self) -> bool {
+        !matches!(self.echo_cancel, EchoCancel::Disabled)
+    }
+
     fn setup(&mut self) -> Result<()> {
-        let output = get_cmd!(
-            "pactl",
-            "load-module",
-            "module-null-sink",
-            "sink_name=dcamctl_webcam",
-            "format=S16LE rate=44100 channels=1",
-            "sink_properties=\"device.description='dcamctl (raw)'\""
-             => "failed to load dcamctl audio module");
-
-        self.sink_id = String::from_utf8_lossy(&output.stdout)
-            .trim()
-            .parse()
-            .context("failed to parse sink_id")?;
+        // recorded first, so that the modules can be recognized after a crash
+        state::update(|s| {
+            s.sink_name = Some(self.names.sink.clone());
+            s.ec_source_name = Some(self.names.ec_source.clone());
+        });
+        self.sink_id = self
+            .pulse
+            .load_module(
+                "module-null-sink",
+                &[
+                    &format!("sink_name={}", self.names.sink),
+                    &sample_spec(&self.format),
+                    &format!(
+                        "sink_properties=\"device.description='{} (raw)'\"",
+                        self.names.description
+                    ),
+                ],
+            )
+            .context("failed to load dcamctl audio module")?;
         trace!("sink_id = {}", self.sink_id);
+        let sink_id = self.sink_id;
+        state::update(|s| s.modules.push(sink_id));
 
-        self.echo_cancel.setup(&self.default_sink)?;
+        self.echo_cancel.setup(
+            &mut self.pulse,
+            &self.sink_master,
+            &self.format,
+            &self.tuning,
+            &self.names,
+        )?;
 
-        match self.echo_cancel {
-            EchoCancel::Pulseaudio { .. } => {
-                run_cmd!("pactl", "set-default-source", "dcamctl_webcam_ec_src" => "failed to set dcamctl as default source");
-                run_cmd!("pactl", "set-default-sink", "dcamctl_webcam_ec_aout" => "failed to set dcamctl as default sink");
+        // the virtual microphone, and the speaker to use for the echo cancellation
+        let (source, sink) = match self.echo_cancel {
+            EchoCancel::Pulseaudio { .. } | EchoCancel::PipeWireNative { .. } => (
+                (
+                    self.names.ec_source.clone(),
+                    self.names.ec_source_description(),
+                ),
+                Some((self.names.ec_sink.clone(), self.names.ec_sink_description())),
+            ),
+            EchoCancel::Disabled => (
+                (
+                    format!("{}.monitor", self.names.sink),
+                    self.names.description.clone(),
+                ),
+                None,
+            ),
+        };
 
-                info!("set up default audio input 'Webcam Virtual Microphone (EC-cancelled)'");
-                info!("set up default audio output 'Default Audio Out (EC-cancelled with Webcam Virtual Microphone)'");
+        self.mic_source = source.0.clone();
 
-                show!(Warn, "\rSetting temporary defaults:");
-                show!(
-                    Warn,
-                    "  Microphone: Webcam Virtual Microphone (EC-cancelled)\r"
-                );
-                show!(
-                    Warn,
-                    "  Speaker   : Default Audio Out (EC-cancelled with Webcam Virtual Microphone)\r"
-                );
+        if self.defaults == AudioDefaults::Keep {
+            info!("set up audio input '{}'", source.1);
+
+            show!(Warn, "\rKeeping audio defaults, select manually:");
+            show!(Warn, "  Microphone: {}\r", (source.1));
+            if let Some((_, description)) = sink {
+                info!("set up audio output '{}'", description);
+                show!(Warn, "  Speaker   : {}\r", description);
             }
-            EchoCancel::Disabled => {
-                run_cmd!("pactl", "set-default-source", "dcamctl_webcam.monitor" => "failed to set dcamctl as default source");
 
-                info!("set up default audio input 'Webcam Virtual Microphone'");
+            return Ok(());
+        }
 
-                show!(Warn, "Setting temporary defaults:\r");
-                show!(Warn, "  Microphone: Webcam Virtual Microphone\r");
-            }
+        state::update(|s| {
+            s.default_source = Some(self.default_source.clone());
+            s.default_sink = Some(self.default_sink.clone());
+        });
+        self.pulse
+            .set_default_source(&source.0)
+            .context("failed to set dcamctl as default source")?;
+        info!("set up default audio input '{}'", source.1);
+        if let Some((name, _)) = &sink {
+            self.pulse
+                .set_default_sink(name)
+                .context("failed to set dcamctl as default sink")?;
+        }
+
+        show!(Warn, "\rSetting temporary defaults:");
+        show!(Warn, "  Microphone: {}\r", (source.1));
+        if let Some((_, description)) = sink {
+            info!("set up default audio output '{}'", description);
+            show!(Warn, "  Speaker   : {}\r", description);
         }
 
         Ok(())
@@ -334,80 +1076,374 @@ impl AudioSupport {
 
 impl Drop for AudioSupport {
     fn drop(&mut self) {
-        run_cmd!("pactl", "set-default-source", &self.default_source =>
-            "failed to reset default source",
-            |s| warn!(
-                "error trying to set default source back to {} (returned {})",
-                self.default_source, s
-            )
-        );
+        if self.defaults == AudioDefaults::Override {
+            if let Err(e) = self.pulse.set_default_source(&self.default_source) {
+                warn!(
+                    "error trying to set default source back to {}: {:#}",
+                    self.default_source, e
+                );
+            }
 
-        run_cmd!("pactl", "set-default-sink", &self.default_sink =>
-            "failed to reset default sink",
-            |s| warn!(
-                "error trying to set default sink back to {} (returned {})",
-                self.default_sink, s
-            )
-        );
+            if let Err(e) = self.pulse.set_default_sink(&self.default_sink) {
+                warn!(
+                    "error trying to set default sink back to {}: {:#}",
+                    self.default_sink, e
+                );
+            }
+        }
 
-        run_cmd!("pactl", "unload-module", &self.sink_id.to_string() =>
-            "failed to unload dcamctl audio module",
-            |s| warn!(
-                "error trying to unload webcam audio module, id={} (returned {})",
-                self.sink_id, s
-            )
-        );
+        if let Err(e) = self.pulse.unload_module(self.sink_id) {
+            warn!(
+                "error trying to unload webcam audio module, id={}: {:#}",
+                self.sink_id, e
+            );
+        }
+
+        self.echo_cancel.teardown(&mut self.pulse);
+
+        state::update(|s| {
+            s.modules.clear();
+            s.default_source = None;
+            s.default_sink = None;
+            s.echo_cancel_pid = None;
+            s.echo_cancel_conf = None;
+            s.sink_name = None;
+            s.ec_source_name = None;
+        });
     }
 }
 
 impl EchoCancel {
-    fn setup(&mut self, default_sink: &str) -> Result<()> {
+    fn setup(
+        &mut self,
+        pulse: &mut pulse::Pulse,
+        sink_master: &str,
+        format: &AudioConfig,
+        tuning: &EchoCancelConfig,
+        names: &AudioNames,
+    ) -> Result<()> {
         match self {
             EchoCancel::Pulseaudio { cancel_sink_id } => {
-                let output = get_cmd!(
-                    "pactl",
-                    "load-module",
-                    "module-echo-cancel",
-                    "source_master=dcamctl_webcam.monitor",
-                    "source_name=dcamctl_webcam_ec_src",
-                    "source_properties=\"device.description='Webcam Virtual Microphone (EC-cancelled)'\"",
-                    &format!("sink_master={}", default_sink),
-                    "sink_name=dcamctl_webcam_ec_aout",
-                    "sink_properties=\"device.description='Default Audio Out (EC-cancelled with Webcam Virtual Microphone)'\"",
-                    "format=S16LE rate=44100 channels=1",
-                    "aec_method=\"webrtc\"",
-                    "save_aec=true",
-                    "use_volume_sharing=true"
-                    => "failed to load echo cancellation module"
-                );
-
-                let new_cancel_sink_id: u32 = String::from_utf8_lossy(&output.stdout)
-                    .trim()
-                    .parse()
-                    .context("failed to parse cancel_sink_id")?;
+                let new_cancel_sink_id = pulse
+                    .load_module(
+                        "module-echo-cancel",
+                        &[
+                            &format!("source_master={}.monitor", names.sink),
+                            &format!("source_name={}", names.ec_source),
+                            &format!(
+                                "source_properties=\"device.description='{}'\"",
+                                names.ec_source_description()
+                            ),
+                            &format!("sink_master={}", sink_master),
+                            &format!("sink_name={}", names.ec_sink),
+                            &format!(
+                                "sink_properties=\"device.description='{}'\"",
+                                names.ec_sink_description()
+                            ),
+                            &sample_spec(format),
+                            &aec_args(tuning),
+                            "save_aec=true",
+                            "use_volume_sharing=true",
+                        ],
+                    )
+                    .context("failed to load echo cancellation module")?;
                 trace!("cancel_sink_id={}", new_cancel_sink_id);
+                state::update(|s| s.modules.push(new_cancel_sink_id));
                 *cancel_sink_id = new_cancel_sink_id;
             }
+            EchoCancel::PipeWireNative { process, conf_path } => {
+                let path = runtime_dir()?.join(format!("{}-echo-cancel.conf", names.sink));
+                std::fs::write(
+                    &path,
+                    pipewire_echo_cancel_conf(sink_master, format, tuning, names),
+                )
+                .with_context(|| format!("failed to write {}", path.display()))?;
+                *conf_path = Some(path.clone());
+                state::update(|s| s.echo_cancel_conf = Some(path.clone()));
+
+                let child = std::process::Command::new("pipewire")
+                    .arg("-c")
+                    .arg(&path)
+                    .stdout(std::process::Stdio::null())
+                    .stderr(std::process::Stdio::null())
+                    .spawn()
+                    .context("failed to start pipewire echo cancellation")?;
+                let pid = child.id();
+                trace!("echo cancellation pid={}", pid);
+                state::update(|s| s.echo_cancel_pid = Some(pid));
+                *process = Some(child);
+
+                // the nodes are created asynchronously by the new process
+                wait_for_devices(pulse, &names.ec_source, &names.ec_sink)?;
+            }
             EchoCancel::Disabled => {}
         };
 
         Ok(())
     }
-}
 
-impl Drop for EchoCancel {
-    fn drop(&mut self) {
+    fn teardown(&mut self, pulse: &mut pulse::Pulse) {
         match self {
             EchoCancel::Pulseaudio { cancel_sink_id } => {
-                run_cmd!("pactl", "unload-module", &cancel_sink_id.to_string() =>
-                    "failed to unload echo cancellation module",
-                    |s| warn!(
-                        "error trying to unload echo cancelation module, id={} (returned {})",
-                        cancel_sink_id, s
-                    )
-                );
+                if let Err(e) = pulse.unload_module(*cancel_sink_id) {
+                    warn!(
+                        "error trying to unload echo cancelation module, id={}: {:#}",
+                        cancel_sink_id, e
+                    );
+                }
+            }
+            EchoCancel::PipeWireNative { process, conf_path } => {
+                if let Some(mut p) = process.take() {
+                    if let Err(e) = p.kill().and_then(|_| p.wait()) {
+                        warn!("error trying to stop pipewire echo cancellation: {}", e);
+                    }
+                }
+                if let Some(path) = conf_path.take() {
+                    if let Err(e) = std::fs::remove_file(&path) {
+                        warn!("error trying to remove {}: {}", path.display(), e);
+                    }
+                }
             }
             EchoCancel::Disabled => {}
         }
     }
 }
+
+/// Sample specification of the audio modules, as pulseaudio module arguments.
+fn sample_spec(format: &AudioConfig) -> String {
+    format!(
+        "format=S16LE rate={} channels={}",
+        format.rate, format.channels
+    )
+}
+
+/// Uses the configured resolution, or asks the device for its current one.
+async fn detect_resolution(conf: &ProgramConfig) -> Resolution {
+    match &conf.resolution {
+        Some(r) => r.clone(),
+        None if conf.test_source => Resolution {
+            height: 480,
+            width: 640,
+        },
+        None => match control::get_cam_info(conf.port, false).await {
+            Ok(cam_info) => {
+                debug!(
+                    "autodetecting default resolution of {}",
+                    cam_info.curvals.video_size
+                );
+                cam_info.curvals.video_size
+            }
+            Err(e) => {
+                debug!("{}", e);
+                warn!("failed to autodetect device resolution; using 640x480");
+                Resolution {
+                    height: 480,
+                    width: 640,
+                }
+            }
+        },
+    }
+}
+
+/// Name of the gstreamer element wrapping the RNNoise LADSPA plugin.
+fn rnnoise_element(channels: u8) -> Result<&'static str> {
+    let name = match channels {
+        1 => "ladspa-librnnoise-ladspa-so-noise-suppressor-mono",
+        2 => "ladspa-librnnoise-ladspa-so-noise-suppressor-stereo",
+        _ => bail!("RNNoise only supports mono and stereo audio"),
+    };
+    if gstreamer::ElementFactory::find(name).is_none() {
+        bail!(
+            "RNNoise noise suppression needs the RNNoise LADSPA plugin and the gstreamer ladspa plugin (missing element '{}')",
+            name
+        );
+    }
+
+    Ok(name)
+}
+
+/// Method and tuning arguments of module-echo-cancel.
+fn aec_args(tuning: &EchoCancelConfig) -> String {
+    let b = |v: bool| if v { 1 } else { 0 };
+    match tuning.method {
+        AecMethod::Webrtc => format!(
+            "aec_method=\"webrtc\" aec_args=\"noise_suppression={} analog_gain_control={} digital_gain_control={} high_pass_filter={} extended_filter={}\"",
+            b(tuning.noise_suppression),
+            b(tuning.analog_gain_control),
+            b(tuning.digital_gain_control),
+            b(tuning.high_pass_filter),
+            b(tuning.extended_filter)
+        ),
+        // speex has no high pass or extended filter
+        AecMethod::Speex => format!(
+            "aec_method=\"speex\" aec_args=\"denoise={} agc={}\"",
+            b(tuning.noise_suppression),
+            b(tuning.analog_gain_control || tuning.digital_gain_control)
+        ),
+    }
+}
+
+/// Finds the audio output with the given name, or with a description containing it.
+fn find_sink(pulse: &mut pulse::Pulse, wanted: &str) -> Result<String> {
+    let sinks = pulse.list_sinks()?;
+    if sinks.iter().any(|s| s.name == wanted) {
+        return Ok(wanted.to_owned());
+    }
+
+    let lowercase = wanted.to_lowercase();
+    let matching: Vec<_> = sinks
+        .iter()
+        .filter(|s| s.description.to_lowercase().contains(&lowercase))
+        .collect();
+    match matching.as_slice() {
+        [sink] => Ok(sink.name.clone()),
+        [] => bail!(
+            "no audio output matches '{}', run 'dcamctl audio-devices' to list them",
+            wanted
+        ),
+        _ => bail!(
+            "several audio outputs match '{}': {}",
+            wanted,
+            matching
+                .iter()
+                .map(|s| format!("'{}'", s.description))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    }
+}
+
+/// Prints the audio outputs and inputs, to pick one for the echo cancellation.
+pub fn list_audio_devices() -> Result<()> {
+    let mut pulse = pulse::Pulse::connect()?;
+    let info = pulse
+        .server_info()
+        .context("failed to get pulseaudio info")?;
+
+    println!("Audio outputs:");
+    for sink in pulse.list_sinks()? {
+        let default = if sink.name == info.default_sink {
+            " [default]"
+        } else {
+            ""
+        };
+        println!("  {} ({}){}", sink.name, sink.description, default);
+    }
+    println!("Audio inputs:");
+    for source in pulse.list_sources()? {
+        let default = if source.name == info.default_source {
+            " [default]"
+        } else {
+            ""
+        };
+        println!("  {} ({}){}", source.name, source.description, default);
+    }
+
+    Ok(())
+}
+
+fn has_pipewire_command() -> bool {
+    std::process::Command::new("pipewire")
+        .arg("--version")
+        .stdout(std::process::Stdio::null())
+        .status()
+        .map(|s| s.success())
+        .unwrap_or(false)
+}
+
+/// Waits for a source with the given name to be visible from pulseaudio.
+fn wait_for_devices(pulse: &mut pulse::Pulse, source: &str, sink: &str) -> Result<()> {
+    for _ in 0..30 {
+        if pulse.has_source(source)? && pulse.has_sink(sink)? {
+            return Ok(());
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+
+    bail!(
+        "timed out waiting for audio devices '{}' and '{}'",
+        source,
+        sink
+    )
+}
+
+/// Configuration for a standalone pipewire process running the echo canceller.
+fn pipewire_echo_cancel_conf(
+    sink_master: &str,
+    format: &AudioConfig,
+    tuning: &EchoCancelConfig,
+    names: &AudioNames,
+) -> String {
+    format!(
+        r#"context.properties = {{
+    log.level = 0
+}}
+context.spa-libs = {{
+    audio.convert.* = audioconvert/libspa-audioconvert
+    support.*       = support/libspa-support
+}}
+context.modules = [
+    {{ name = libpipewire-module-rt flags = [ ifexists nofail ] }}
+    {{ name = libpipewire-module-protocol-native }}
+    {{ name = libpipewire-module-client-node }}
+    {{ name = libpipewire-module-adapter }}
+    {{ name = libpipewire-module-echo-cancel
+        args = {{
+            library.name = aec/libspa-aec-webrtc
+            audio.rate = {rate}
+            audio.channels = {channels}
+            aec.args = {{
+                webrtc.noise_suppression = {ns}
+                webrtc.gain_control = {agc}
+                webrtc.high_pass_filter = {hpf}
+                webrtc.extended_filter = {ext}
+            }}
+            capture.props = {{
+                node.name = "{name}_ec_capture"
+                node.target = "{name}"
+                target.object = "{name}"
+                stream.capture.sink = true
+                node.passive = true
+            }}
+            source.props = {{
+                node.name = "{ec_source}"
+                node.description = "{ec_source_description}"
+            }}
+            sink.props = {{
+                node.name = "{ec_sink}"
+                node.description = "{ec_sink_description}"
+            }}
+            playback.props = {{
+                node.name = "{name}_ec_playback"
+                node.target = "{sink}"
+                target.object = "{sink}"
+                node.passive = true
+            }}
+        }}
+    }}
+]
+"#,
+        sink = sink_master,
+        name = names.sink,
+        ec_source = names.ec_source,
+        ec_source_description = names.ec_source_description(),
+        ec_sink = names.ec_sink,
+        ec_sink_description = names.ec_sink_description(),
+        rate = format.rate,
+        channels = format.channels,
+        ns = tuning.noise_suppression,
+        agc = tuning.analog_gain_control || tuning.digital_gain_control,
+        hpf = tuning.high_pass_filter,
+        ext = tuning.extended_filter
+    )
+}
+
+/// Directory for the temporary files of dcamctl.
+fn runtime_dir() -> Result<PathBuf> {
+    let dir = directories_next::ProjectDirs::from("rs", "", "Dcamctl")
+        .and_then(|p| p.runtime_dir().map(|d| d.to_owned()))
+        .unwrap_or_else(|| std::env::temp_dir().join("dcamctl"));
+    std::fs::create_dir_all(&dir).with_context(|| format!("failed to create {}", dir.display()))?;
+
+    Ok(dir)
+}
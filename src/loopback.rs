@@ -0,0 +1,344 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    os::unix::io::AsRawFd,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use log::*;
+
+const SYSFS_V4L: &str = "/sys/class/video4linux";
+const LOOPBACK_DRIVER: &str = "v4l2 loopback";
+const LOOPBACK_CONTROL: &str = "/dev/v4l2loopback";
+
+/// `struct v4l2_capability` from `linux/videodev2.h`.
+#[repr(C)]
+#[derive(Default)]
+struct V4l2Capability {
+    driver: [u8; 16],
+    card: [u8; 32],
+    bus_info: [u8; 32],
+    version: u32,
+    capabilities: u32,
+    device_caps: u32,
+    reserved: [u32; 3],
+}
+
+nix::ioctl_read!(vidioc_querycap, b'V', 0, V4l2Capability);
+
+/// `struct v4l2_loopback_config` from `linux/v4l2loopback.h`.
+#[repr(C)]
+struct LoopbackConfig {
+    output_nr: i32,
+    capture_nr: i32,
+    card_label: [u8; 32],
+    min_width: u32,
+    max_width: u32,
+    min_height: u32,
+    max_height: u32,
+    max_buffers: i32,
+    max_openers: i32,
+    debug: i32,
+    announce_all_caps: i32,
+}
+
+nix::ioctl_write_ptr_bad!(loopback_ctl_add, 0x4C80, LoopbackConfig);
+nix::ioctl_write_int_bad!(loopback_ctl_remove, 0x4C81);
+
+/// A video device, as seen in sysfs.
+#[derive(Debug)]
+pub struct VideoDevice {
+    pub path: PathBuf,
+    pub name: String,
+    sysfs: PathBuf,
+}
+
+impl VideoDevice {
+    fn from_sysfs(sysfs: PathBuf) -> Option<VideoDevice> {
+        let node = sysfs.file_name()?.to_owned();
+        let name = fs::read_to_string(sysfs.join("name"))
+            .map(|n| n.trim().to_owned())
+            .unwrap_or_default();
+
+        Some(VideoDevice {
+            path: Path::new("/dev").join(node),
+            name,
+            sysfs,
+        })
+    }
+
+    /// Whether the device is exposed by the v4l2loopback kernel module.
+    pub fn is_loopback(&self) -> bool {
+        match query_driver(&self.path) {
+            Ok(driver) => driver == LOOPBACK_DRIVER,
+            Err(e) => {
+                // we might not be allowed to open the device, but only v4l2loopback
+                // devices have this attribute
+                debug!("{}", e);
+                self.sysfs.join("max_openers").exists()
+            }
+        }
+    }
+
+    /// Whether something is already writing to this loopback device.
+    ///
+    /// v4l2loopback only reports a format once a producer has set one.
+    pub fn has_producer(&self) -> bool {
+        fs::read_to_string(self.sysfs.join("format"))
+            .map(|f| !f.trim().is_empty())
+            .unwrap_or(false)
+    }
+}
+
+impl std::fmt::Display for VideoDevice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ('{}')", self.path.display(), self.name)
+    }
+}
+
+fn query_driver(path: &Path) -> Result<String> {
+    let file = File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    let mut cap = V4l2Capability::default();
+    unsafe { vidioc_querycap(file.as_raw_fd(), &mut cap) }
+        .with_context(|| format!("failed to query capabilities of {}", path.display()))?;
+
+    let len = cap.driver.iter().position(|&c| c == 0).unwrap_or(16);
+    Ok(String::from_utf8_lossy(&cap.driver[..len]).into_owned())
+}
+
+fn video_devices() -> Result<Vec<VideoDevice>> {
+    let mut devices: Vec<VideoDevice> = fs::read_dir(SYSFS_V4L)
+        .with_context(|| format!("failed to list video devices in {}", SYSFS_V4L))?
+        .filter_map(|e| e.ok())
+        .filter_map(|e| VideoDevice::from_sysfs(e.path()))
+        .collect();
+    devices.sort_by_key(|d| {
+        d.path
+            .to_string_lossy()
+            .trim_start_matches("/dev/video")
+            .parse::<u32>()
+            .unwrap_or(u32::MAX)
+    });
+
+    Ok(devices)
+}
+
+fn loopback_devices() -> Result<Vec<VideoDevice>> {
+    Ok(video_devices()?
+        .into_iter()
+        .filter(|d| d.is_loopback())
+        .collect())
+}
+
+fn list(devices: &[VideoDevice]) -> String {
+    devices
+        .iter()
+        .map(|d| d.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Counts the other processes that currently have the given device open.
+///
+/// Processes we aren't allowed to look at (from other users) are ignored.
+pub fn count_consumers(device: &Path) -> usize {
+    let device = match device.canonicalize() {
+        Ok(d) => d,
+        Err(_) => return 0,
+    };
+    let own_pid = std::process::id().to_string();
+
+    let procs = match fs::read_dir("/proc") {
+        Ok(p) => p,
+        Err(e) => {
+            debug!("failed to list processes: {}", e);
+            return 0;
+        }
+    };
+
+    procs
+        .filter_map(|e| e.ok())
+        .filter(|e| {
+            let name = e.file_name();
+            let name = name.to_string_lossy();
+            name != own_pid && name.chars().all(|c| c.is_ascii_digit())
+        })
+        .filter(|e| {
+            fs::read_dir(e.path().join("fd"))
+                .map(|fds| {
+                    fds.filter_map(|fd| fd.ok())
+                        .any(|fd| fs::read_link(fd.path()).map_or(false, |l| l == device))
+                })
+                .unwrap_or(false)
+        })
+        .count()
+}
+
+/// Checks that the given device is a v4l2loopback device.
+pub fn check_device(path: &Path) -> Result<()> {
+    let real = path
+        .canonicalize()
+        .with_context(|| match loopback_devices() {
+            Ok(l) if !l.is_empty() => format!(
+                "video device {} doesn't exist; available v4l2loopback devices are: {}",
+                path.display(),
+                list(&l)
+            ),
+            _ => format!("video device {} doesn't exist", path.display()),
+        })?;
+    let node = real
+        .file_name()
+        .ok_or_else(|| anyhow!("invalid video device {}", path.display()))?;
+    let device = VideoDevice::from_sysfs(Path::new(SYSFS_V4L).join(node))
+        .filter(|d| d.sysfs.exists())
+        .ok_or_else(|| anyhow!("{} is not a video device", path.display()))?;
+
+    if !device.is_loopback() {
+        let others = loopback_devices()?;
+        if others.is_empty() {
+            bail!(
+                "{} is not a v4l2loopback device, and there are none; check the options of the v4l2loopback kernel module",
+                device
+            );
+        } else {
+            bail!(
+                "{} is not a v4l2loopback device; use one of {} with --device, or '--device auto'",
+                device,
+                list(&others)
+            );
+        }
+    }
+
+    if device.has_producer() {
+        warn!("{} is already being used by another program", device);
+    }
+
+    debug!("using v4l2loopback device {}", device);
+
+    Ok(())
+}
+
+/// Finds the first v4l2loopback device that nothing writes to, optionally with a label
+/// containing the given string.
+pub fn find_device(label: Option<&str>) -> Result<PathBuf> {
+    let devices = loopback_devices()?;
+    if devices.is_empty() {
+        bail!("no v4l2loopback device found; check the options of the v4l2loopback kernel module");
+    }
+
+    let label = label.map(|l| l.to_lowercase());
+    let device = devices
+        .iter()
+        .filter(|d| match &label {
+            Some(l) => d.name.to_lowercase().contains(l),
+            None => true,
+        })
+        .find(|d| !d.has_producer());
+
+    match (device, label) {
+        (Some(d), _) => {
+            info!("autodetected v4l2loopback device {}", d);
+            Ok(d.path.clone())
+        }
+        (None, Some(l)) => bail!(
+            "no free v4l2loopback device with a label matching '{}'; available devices are: {}",
+            l,
+            list(&devices)
+        ),
+        (None, None) => bail!(
+            "all v4l2loopback devices are already in use: {}",
+            list(&devices)
+        ),
+    }
+}
+
+/// A v4l2loopback device created by dcamctl, removed when dropped.
+#[derive(Debug)]
+pub struct LoopbackDevice {
+    nr: i32,
+    path: PathBuf,
+}
+
+impl LoopbackDevice {
+    /// Creates a new v4l2loopback device with the given label, through the control device
+    /// of v4l2loopback (0.12.6+).
+    pub fn create(label: &str, exclusive_caps: bool) -> Result<LoopbackDevice> {
+        let control = open_control()?;
+
+        let mut card_label = [0; 32];
+        // keep the label NUL-terminated
+        let len = label.len().min(card_label.len() - 1);
+        card_label[..len].copy_from_slice(&label.as_bytes()[..len]);
+
+        // negative or zero values mean using the module defaults
+        let conf = LoopbackConfig {
+            output_nr: -1,
+            capture_nr: -1,
+            card_label,
+            min_width: 0,
+            max_width: 0,
+            min_height: 0,
+            max_height: 0,
+            max_buffers: -1,
+            max_openers: -1,
+            debug: -1,
+            announce_all_caps: if exclusive_caps { 0 } else { 1 },
+        };
+        let nr = unsafe { loopback_ctl_add(control.as_raw_fd(), &conf) }
+            .context("failed to create v4l2loopback device")?;
+
+        let path = PathBuf::from(format!("/dev/video{}", nr));
+        let device = LoopbackDevice { nr, path };
+
+        // udev needs a little time to create the device node
+        let start = Instant::now();
+        while !device.path.exists() {
+            if start.elapsed() > Duration::from_secs(2) {
+                bail!(
+                    "created v4l2loopback device {} never appeared",
+                    device.path.display()
+                );
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+
+        info!(
+            "created v4l2loopback device {} ('{}')",
+            device.path.display(),
+            label
+        );
+
+        Ok(device)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for LoopbackDevice {
+    fn drop(&mut self) {
+        let removed = open_control().and_then(|control| {
+            unsafe { loopback_ctl_remove(control.as_raw_fd(), self.nr) }
+                .context("failed to remove v4l2loopback device")
+        });
+        match removed {
+            Ok(_) => debug!("removed v4l2loopback device {}", self.path.display()),
+            Err(e) => warn!("{}: {}", self.path.display(), e),
+        }
+    }
+}
+
+fn open_control() -> Result<File> {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(LOOPBACK_CONTROL)
+        .with_context(|| {
+            format!(
+                "failed to open {} (creating devices needs v4l2loopback 0.12.6+ and write access to it)",
+                LOOPBACK_CONTROL
+            )
+        })
+}
@@ -2,8 +2,13 @@ use std::path::Path;
 
 use anyhow::{anyhow, bail, Result};
 use clap::{FromArgMatches, IntoApp};
-use dcamctl::{cli::ProgramOptions, config::*};
-use dcamctl::{show, AdbServer, AudioSupport, Dcam};
+#[cfg(feature = "rtsp")]
+use dcamctl::RtspServer;
+use dcamctl::{
+    cli::{ProgramCommand, ProgramOptions},
+    config::*,
+};
+use dcamctl::{loopback, show, AdbServer, AudioSupport, Dcam};
 use directories_next::ProjectDirs;
 use env_logger::{Builder, Env};
 use log::*;
@@ -57,22 +62,86 @@ fn main() -> Result<()> {
 }
 
 async fn run(options: ProgramOptions) -> Result<ReturnCode> {
-    let conf = make_config(options)?;
+    match options.command {
+        Some(ProgramCommand::Cleanup) => {
+            dcamctl::cleanup()?;
+            return Ok(0);
+        }
+        Some(ProgramCommand::AudioDevices) => {
+            dcamctl::list_audio_devices()?;
+            return Ok(0);
+        }
+        None => {}
+    }
+
+    let mut conf = make_config(options)?;
+    if conf.no_video {
+        if conf.no_audio {
+            bail!("nothing to do with both audio and video disabled");
+        }
+        if conf.rtsp.enabled || conf.http.enabled || conf.push.url.is_some() {
+            bail!("RTSP, HTTP and push outputs need video");
+        }
+    }
+    dcamctl::check_leftovers()?;
 
-    check_kernel_module()?;
+    let server = if conf.test_source {
+        debug!("using test source, skipping adb setup");
+        None
+    } else {
+        AdbServer::init()?;
+        Some(AdbServer::connect(conf.port, conf.serial.as_deref())?)
+    };
+    let model = server.as_ref().and_then(|s| s.device_model());
 
-    AdbServer::init()?;
-    let _server = AdbServer::connect(conf.port, conf.serial.as_deref())?;
+    let _loopback = if conf.output == Output::V4l2 && !conf.no_video {
+        check_kernel_module()?;
+
+        if conf.create_device {
+            let label = match &conf.device_label {
+                Some(l) => l.clone(),
+                None => match &model {
+                    Some(model) => format!("dcamctl ({})", model),
+                    None => "dcamctl".to_owned(),
+                },
+            };
+            let device = loopback::LoopbackDevice::create(&label, conf.exclusive_caps)?;
+            conf.device = device.path().to_owned();
+            Some(device)
+        } else {
+            if conf.device == Path::new("auto") {
+                conf.device = loopback::find_device(conf.device_label.as_deref())?;
+            } else {
+                loopback::check_device(&conf.device)?;
+            }
+            None
+        }
+    } else {
+        None
+    };
 
     gstreamer::init()?;
 
     let audio = if conf.no_audio {
         None
     } else {
-        AudioSupport::new(!conf.no_echo_cancel)?
+        AudioSupport::new(&conf, model.as_deref())?
+    };
+    if conf.no_video && audio.is_none() {
+        bail!("audio support isn't available, nothing to do without video");
+    }
+    #[cfg(feature = "rtsp")]
+    let _rtsp = if conf.rtsp.enabled {
+        Some(RtspServer::start(&conf.rtsp, audio.is_some())?)
+    } else {
+        None
     };
-    let mut pipeline =
-        Dcam::setup(audio, &conf.device, conf.resolution, conf.port, conf.flip).await?;
+    #[cfg(not(feature = "rtsp"))]
+    if conf.rtsp.enabled {
+        bail!("dcamctl was built without RTSP support");
+    }
+
+    let mut pipeline = Dcam::setup(audio, &conf, model.as_deref()).await?;
 
     pipeline.run().await?;
 
@@ -135,15 +204,43 @@ fn make_config(options: ProgramOptions) -> Result<ProgramConfig> {
 
     conf = set_conf_from_options(conf, &options.port.map(|p| p.to_string()), "port")?;
     conf = set_conf_from_options(conf, &options.device, "device")?;
+    conf = set_conf_from_options(conf, &options.output, "output")?;
     conf = set_conf_from_options(conf, &options.resolution, "resolution")?;
     conf = set_conf_from_options(conf, &options.flip, "flip")?;
     conf = set_conf_from_options(conf, &options.serial, "serial")?;
+    conf = set_conf_from_options(conf, &options.latency, "latency")?;
+    conf = set_conf_from_options(conf, &options.audio_defaults, "audio_defaults")?;
+    conf = set_conf_from_options(conf, &options.push, "push.url")?;
     if options.no_audio {
         conf = conf.set_override("no_audio", Some(true))?;
     }
+    if options.no_video {
+        conf = conf.set_override("no_video", Some(true))?;
+    }
     if options.no_echo_cancel {
         conf = conf.set_override("no_echo_cancel", Some(true))?;
     }
+    if options.create_device {
+        conf = conf.set_override("create_device", Some(true))?;
+    }
+    if options.pause_when_idle {
+        conf = conf.set_override("pause_when_idle", Some(true))?;
+    }
+    if options.preview {
+        conf = conf.set_override("preview", Some(true))?;
+    }
+    if options.rtsp {
+        conf = conf.set_override("rtsp.enabled", Some(true))?;
+    }
+    if options.http {
+        conf = conf.set_override("http.enabled", Some(true))?;
+    }
+    if options.test_source {
+        conf = conf.set_override("test_source", Some(true))?;
+    }
+    if options.grayscale {
+        conf = conf.set_override("color.grayscale", Some(true))?;
+    }
 
     let conf: ProgramConfig = conf.build()?.try_deserialize()?;
     trace!("full config: {:#?}", conf);
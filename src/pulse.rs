@@ -0,0 +1,532 @@
+#[cfg(feature = "pulse")]
+use std::{cell::RefCell, rc::Rc};
+
+#[cfg(feature = "pulse")]
+use anyhow::anyhow;
+use anyhow::{bail, Context, Result};
+#[cfg(feature = "pulse")]
+use libpulse_binding as libpulse;
+use log::*;
+
+/// What dcamctl needs to know about the sound server.
+#[derive(Debug, Default)]
+pub struct ServerInfo {
+    pub name: String,
+    pub default_sink: String,
+    pub default_source: String,
+}
+
+/// An audio output (sink) or input (source).
+#[derive(Debug, Default)]
+pub struct Device {
+    pub name: String,
+    pub description: String,
+}
+
+/// A loaded module.
+#[derive(Debug, Default)]
+pub struct Module {
+    pub index: u32,
+    pub name: String,
+    pub argument: String,
+}
+
+/// Connection to the PulseAudio (or pipewire-pulse) server.
+///
+/// This talks the native protocol through libpulse when possible, and falls back to calling
+/// `pactl` otherwise.
+pub enum Pulse {
+    #[cfg(feature = "pulse")]
+    Native(Native),
+    Pactl,
+}
+
+impl std::fmt::Debug for Pulse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            #[cfg(feature = "pulse")]
+            Pulse::Native(_) => write!(f, "Native"),
+            Pulse::Pactl => write!(f, "Pactl"),
+        }
+    }
+}
+
+impl Pulse {
+    pub fn connect() -> Result<Pulse> {
+        #[cfg(feature = "pulse")]
+        match Native::connect() {
+            Ok(n) => {
+                debug!("connected to the sound server with libpulse");
+                return Ok(Pulse::Native(n));
+            }
+            Err(e) => debug!("{:#}, falling back to pactl", e),
+        }
+
+        run_cmd!("pactl", "--version" => "unable to find 'pactl' command");
+        Ok(Pulse::Pactl)
+    }
+
+    pub fn server_info(&mut self) -> Result<ServerInfo> {
+        match self {
+            #[cfg(feature = "pulse")]
+            Pulse::Native(n) => n.server_info(),
+            Pulse::Pactl => {
+                let output = get_cmd!("pactl", "info" => "failed to get pulseaudio info");
+                let out = String::from_utf8_lossy(&output.stdout);
+
+                let mut info = ServerInfo::default();
+                for l in out.lines() {
+                    let mut l = l.split(": ");
+                    match (l.next(), l.next()) {
+                        (Some("Server Name"), Some(name)) => info.name.push_str(name),
+                        (Some("Default Sink"), Some(sink)) => info.default_sink.push_str(sink),
+                        (Some("Default Source"), Some(source)) => {
+                            info.default_source.push_str(source)
+                        }
+                        _ => {}
+                    }
+                }
+
+                Ok(info)
+            }
+        }
+    }
+
+    pub fn set_default_sink(&mut self, name: &str) -> Result<()> {
+        match self {
+            #[cfg(feature = "pulse")]
+            Pulse::Native(n) => n.set_default(name, false),
+            Pulse::Pactl => {
+                run_cmd!("pactl", "set-default-sink", name => "failed to set default sink");
+                Ok(())
+            }
+        }
+    }
+
+    pub fn set_default_source(&mut self, name: &str) -> Result<()> {
+        match self {
+            #[cfg(feature = "pulse")]
+            Pulse::Native(n) => n.set_default(name, true),
+            Pulse::Pactl => {
+                run_cmd!("pactl", "set-default-source", name => "failed to set default source");
+                Ok(())
+            }
+        }
+    }
+
+    /// Loads a module and returns its index.
+    pub fn load_module(&mut self, name: &str, args: &[&str]) -> Result<u32> {
+        let args = args.join(" ");
+        match self {
+            #[cfg(feature = "pulse")]
+            Pulse::Native(n) => n.load_module(name, &args),
+            Pulse::Pactl => {
+                let output = get_cmd!("pactl", "load-module", name, &args =>
+                    "failed to load audio module");
+                String::from_utf8_lossy(&output.stdout)
+                    .trim()
+                    .parse()
+                    .with_context(|| format!("failed to parse index of module {}", name))
+            }
+        }
+    }
+
+    pub fn unload_module(&mut self, index: u32) -> Result<()> {
+        match self {
+            #[cfg(feature = "pulse")]
+            Pulse::Native(n) => n.unload_module(index),
+            Pulse::Pactl => {
+                run_cmd!("pactl", "unload-module", &index.to_string() =>
+                    "failed to unload audio module");
+                Ok(())
+            }
+        }
+    }
+
+    pub fn list_modules(&mut self) -> Result<Vec<Module>> {
+        match self {
+            #[cfg(feature = "pulse")]
+            Pulse::Native(n) => n.list_modules(),
+            Pulse::Pactl => {
+                let output = get_cmd!("pactl", "list", "short", "modules" =>
+                    "failed to list audio modules");
+                Ok(String::from_utf8_lossy(&output.stdout)
+                    .lines()
+                    .filter_map(|l| {
+                        let mut l = l.splitn(3, '\t');
+                        Some(Module {
+                            index: l.next()?.parse().ok()?,
+                            name: l.next()?.to_owned(),
+                            argument: l.next().unwrap_or_default().to_owned(),
+                        })
+                    })
+                    .collect())
+            }
+        }
+    }
+
+    pub fn list_sinks(&mut self) -> Result<Vec<Device>> {
+        match self {
+            #[cfg(feature = "pulse")]
+            Pulse::Native(n) => n.list_sinks(),
+            Pulse::Pactl => pactl_list("sinks"),
+        }
+    }
+
+    pub fn list_sources(&mut self) -> Result<Vec<Device>> {
+        match self {
+            #[cfg(feature = "pulse")]
+            Pulse::Native(n) => n.list_sources(),
+            Pulse::Pactl => pactl_list("sources"),
+        }
+    }
+
+    /// Returns true if an application is recording from the source.
+    pub fn source_in_use(&mut self, name: &str) -> Result<bool> {
+        match self {
+            #[cfg(feature = "pulse")]
+            Pulse::Native(n) => n.source_in_use(name),
+            Pulse::Pactl => {
+                let output = get_cmd!("pactl", "list", "short", "sources" =>
+                    "failed to list audio sources");
+                Ok(String::from_utf8_lossy(&output.stdout).lines().any(|l| {
+                    let l: Vec<_> = l.split('\t').collect();
+                    l.get(1) == Some(&name) && l.get(4) == Some(&"RUNNING")
+                }))
+            }
+        }
+    }
+
+    pub fn has_sink(&mut self, name: &str) -> Result<bool> {
+        match self {
+            #[cfg(feature = "pulse")]
+            Pulse::Native(n) => n.has_sink(name),
+            Pulse::Pactl => {
+                let output = get_cmd!("pactl", "list", "short", "sinks" =>
+                    "failed to list audio sinks");
+                Ok(String::from_utf8_lossy(&output.stdout)
+                    .lines()
+                    .any(|l| l.split('\t').nth(1) == Some(name)))
+            }
+        }
+    }
+
+    pub fn has_source(&mut self, name: &str) -> Result<bool> {
+        match self {
+            #[cfg(feature = "pulse")]
+            Pulse::Native(n) => n.has_source(name),
+            Pulse::Pactl => {
+                let output = get_cmd!("pactl", "list", "short", "sources" =>
+                    "failed to list audio sources");
+                Ok(String::from_utf8_lossy(&output.stdout)
+                    .lines()
+                    .any(|l| l.split('\t').nth(1) == Some(name)))
+            }
+        }
+    }
+}
+
+/// Lists the sinks or sources with their descriptions, which `pactl list short` doesn't show.
+fn pactl_list(kind: &str) -> Result<Vec<Device>> {
+    trace!("running 'pactl' with arguments '[\"list\", {:?}]'", kind);
+    // the labels are translated otherwise
+    let output = std::process::Command::new("pactl")
+        .args(&["list", kind])
+        .env("LC_ALL", "C")
+        .output()
+        .with_context(|| format!("failed to list audio {}", kind))?;
+    if !output.status.success() {
+        bail!("failed to list audio {} (got {})", kind, output.status);
+    }
+
+    let mut devices: Vec<Device> = Vec::new();
+    for l in String::from_utf8_lossy(&output.stdout).lines() {
+        let l = l.trim_start();
+        if let Some(name) = l.strip_prefix("Name: ") {
+            devices.push(Device {
+                name: name.to_owned(),
+                ..Device::default()
+            });
+        } else if let (Some(description), Some(device)) =
+            (l.strip_prefix("Description: "), devices.last_mut())
+        {
+            device.description = description.to_owned();
+        }
+    }
+
+    Ok(devices)
+}
+
+#[cfg(feature = "pulse")]
+pub struct Native {
+    // the context needs to be dropped before its mainloop
+    context: libpulse::context::Context,
+    mainloop: libpulse::mainloop::standard::Mainloop,
+}
+
+#[cfg(feature = "pulse")]
+impl Native {
+    fn connect() -> Result<Native> {
+        use libpulse::context::{Context, FlagSet, State};
+        use libpulse::mainloop::standard::Mainloop;
+        use libpulse::proplist::{properties, Proplist};
+
+        let mut proplist = Proplist::new().ok_or_else(|| anyhow!("failed to create proplist"))?;
+        proplist
+            .set_str(properties::APPLICATION_NAME, "dcamctl")
+            .map_err(|_| anyhow!("failed to set application name"))?;
+
+        let mainloop = Mainloop::new().ok_or_else(|| anyhow!("failed to create pulse mainloop"))?;
+        let context = Context::new_with_proplist(&mainloop, "dcamctl", &proplist)
+            .ok_or_else(|| anyhow!("failed to create pulse context"))?;
+        let mut native = Native { context, mainloop };
+
+        native
+            .context
+            .connect(None, FlagSet::NOAUTOSPAWN, None)
+            .context("failed to connect to the sound server")?;
+        loop {
+            native.iterate()?;
+            match native.context.get_state() {
+                State::Ready => break,
+                State::Failed | State::Terminated => {
+                    bail!(
+                        "failed to connect to the sound server: {}",
+                        native.context.errno()
+                    )
+                }
+                _ => {}
+            }
+        }
+
+        Ok(native)
+    }
+
+    fn iterate(&mut self) -> Result<()> {
+        use libpulse::mainloop::standard::IterateResult;
+
+        match self.mainloop.iterate(true) {
+            IterateResult::Success(_) => Ok(()),
+            IterateResult::Quit(_) => bail!("pulse mainloop quit unexpectedly"),
+            IterateResult::Err(e) => Err(e).context("pulse mainloop failed"),
+        }
+    }
+
+    /// Runs the mainloop until the operation completes.
+    fn wait<T: ?Sized>(&mut self, op: libpulse::operation::Operation<T>) -> Result<()> {
+        use libpulse::operation::State;
+
+        loop {
+            match op.get_state() {
+                State::Running => self.iterate()?,
+                State::Done => return Ok(()),
+                State::Cancelled => bail!("pulse operation cancelled: {}", self.context.errno()),
+            }
+        }
+    }
+
+    fn server_info(&mut self) -> Result<ServerInfo> {
+        let info = Rc::new(RefCell::new(ServerInfo::default()));
+        let i = info.clone();
+        let op = self.context.introspect().get_server_info(move |s| {
+            let mut i = i.borrow_mut();
+            i.name = s.server_name.as_deref().unwrap_or_default().to_owned();
+            i.default_sink = s
+                .default_sink_name
+                .as_deref()
+                .unwrap_or_default()
+                .to_owned();
+            i.default_source = s
+                .default_source_name
+                .as_deref()
+                .unwrap_or_default()
+                .to_owned();
+        });
+        self.wait(op)?;
+
+        let info = info.take();
+        Ok(info)
+    }
+
+    fn set_default(&mut self, name: &str, source: bool) -> Result<()> {
+        let success = Rc::new(RefCell::new(false));
+        let s = success.clone();
+        let callback = move |ok| *s.borrow_mut() = ok;
+        let op = if source {
+            self.context.set_default_source(name, callback)
+        } else {
+            self.context.set_default_sink(name, callback)
+        };
+        self.wait(op)?;
+
+        if !*success.borrow() {
+            bail!(
+                "failed to set default to '{}': {}",
+                name,
+                self.context.errno()
+            );
+        }
+
+        Ok(())
+    }
+
+    fn load_module(&mut self, name: &str, args: &str) -> Result<u32> {
+        let index = Rc::new(RefCell::new(libpulse::def::INVALID_INDEX));
+        let i = index.clone();
+        let op = self
+            .context
+            .introspect()
+            .load_module(name, args, move |idx| *i.borrow_mut() = idx);
+        self.wait(op)?;
+
+        let index = *index.borrow();
+        if index == libpulse::def::INVALID_INDEX {
+            bail!("failed to load module {}: {}", name, self.context.errno());
+        }
+
+        Ok(index)
+    }
+
+    fn unload_module(&mut self, index: u32) -> Result<()> {
+        let success = Rc::new(RefCell::new(false));
+        let s = success.clone();
+        let op = self
+            .context
+            .introspect()
+            .unload_module(index, move |ok| *s.borrow_mut() = ok);
+        self.wait(op)?;
+
+        if !*success.borrow() {
+            bail!(
+                "failed to unload module {}: {}",
+                index,
+                self.context.errno()
+            );
+        }
+
+        Ok(())
+    }
+
+    fn list_modules(&mut self) -> Result<Vec<Module>> {
+        use libpulse::callbacks::ListResult;
+
+        let modules = Rc::new(RefCell::new(Vec::new()));
+        let m = modules.clone();
+        let op = self.context.introspect().get_module_info_list(move |r| {
+            if let ListResult::Item(i) = r {
+                m.borrow_mut().push(Module {
+                    index: i.index,
+                    name: i.name.as_deref().unwrap_or_default().to_owned(),
+                    argument: i.argument.as_deref().unwrap_or_default().to_owned(),
+                });
+            }
+        });
+        self.wait(op)?;
+
+        let modules = modules.take();
+        Ok(modules)
+    }
+
+    fn list_sinks(&mut self) -> Result<Vec<Device>> {
+        use libpulse::callbacks::ListResult;
+
+        let devices = Rc::new(RefCell::new(Vec::new()));
+        let d = devices.clone();
+        let op = self.context.introspect().get_sink_info_list(move |r| {
+            if let ListResult::Item(i) = r {
+                d.borrow_mut().push(Device {
+                    name: i.name.as_deref().unwrap_or_default().to_owned(),
+                    description: i.description.as_deref().unwrap_or_default().to_owned(),
+                });
+            }
+        });
+        self.wait(op)?;
+
+        let devices = devices.take();
+        Ok(devices)
+    }
+
+    fn list_sources(&mut self) -> Result<Vec<Device>> {
+        use libpulse::callbacks::ListResult;
+
+        let devices = Rc::new(RefCell::new(Vec::new()));
+        let d = devices.clone();
+        let op = self.context.introspect().get_source_info_list(move |r| {
+            if let ListResult::Item(i) = r {
+                d.borrow_mut().push(Device {
+                    name: i.name.as_deref().unwrap_or_default().to_owned(),
+                    description: i.description.as_deref().unwrap_or_default().to_owned(),
+                });
+            }
+        });
+        self.wait(op)?;
+
+        let devices = devices.take();
+        Ok(devices)
+    }
+
+    fn source_in_use(&mut self, name: &str) -> Result<bool> {
+        use libpulse::callbacks::ListResult;
+        use libpulse::def::SourceState;
+
+        let running = Rc::new(RefCell::new(false));
+        let r = running.clone();
+        let op = self
+            .context
+            .introspect()
+            .get_source_info_by_name(name, move |res| {
+                if let ListResult::Item(i) = res {
+                    *r.borrow_mut() = i.state == SourceState::Running;
+                }
+            });
+        self.wait(op)?;
+
+        let running = *running.borrow();
+        Ok(running)
+    }
+
+    fn has_sink(&mut self, name: &str) -> Result<bool> {
+        use libpulse::callbacks::ListResult;
+
+        let found = Rc::new(RefCell::new(false));
+        let f = found.clone();
+        let op = self
+            .context
+            .introspect()
+            .get_sink_info_by_name(name, move |r| {
+                if let ListResult::Item(_) = r {
+                    *f.borrow_mut() = true;
+                }
+            });
+        self.wait(op)?;
+
+        let found = *found.borrow();
+        Ok(found)
+    }
+
+    fn has_source(&mut self, name: &str) -> Result<bool> {
+        use libpulse::callbacks::ListResult;
+
+        let found = Rc::new(RefCell::new(false));
+        let f = found.clone();
+        let op = self
+            .context
+            .introspect()
+            .get_source_info_by_name(name, move |r| {
+                if let ListResult::Item(_) = r {
+                    *f.borrow_mut() = true;
+                }
+            });
+        self.wait(op)?;
+
+        let found = *found.borrow();
+        Ok(found)
+    }
+}
+
+#[cfg(feature = "pulse")]
+impl Drop for Native {
+    fn drop(&mut self) {
+        self.context.disconnect();
+    }
+}
@@ -0,0 +1,173 @@
+use std::sync::{
+    atomic::{AtomicU64, AtomicU8, Ordering},
+    Arc,
+};
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Result};
+use futures::StreamExt;
+use gstreamer::prelude::*;
+use log::*;
+use tokio::task::JoinHandle;
+
+use crate::config::PushConfig;
+use crate::{PUSH_AUDIO_CHANNEL, PUSH_VIDEO_CHANNEL};
+
+const CONNECTING: u8 = 0;
+const LIVE: u8 = 1;
+const FAILED: u8 = 2;
+
+/// Connection state and bitrate of the push output, shared with the terminal controls.
+#[derive(Debug, Default)]
+pub struct PushStatus {
+    state: AtomicU8,
+    bytes: AtomicU64,
+    kbps: AtomicU64,
+}
+
+impl PushStatus {
+    pub fn describe(&self) -> String {
+        match self.state.load(Ordering::Relaxed) {
+            LIVE => format!("live {} kb/s", self.kbps.load(Ordering::Relaxed)),
+            FAILED => "failed".to_owned(),
+            _ => "connecting".to_owned(),
+        }
+    }
+}
+
+/// Pushes the final video and audio to a SRT or RTMP ingest server.
+///
+/// This runs in its own pipeline (fed from the main one), so that losing the connection to the
+/// server doesn't interrupt the local output.
+pub struct Push {
+    pipeline: gstreamer::Pipeline,
+    status: Arc<PushStatus>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl Push {
+    pub fn start(conf: &PushConfig, url: &str, audio: bool) -> Result<Push> {
+        // flv can't carry opus, so AAC is used for RTMP
+        let (mux, sink, sink_property, audio_enc, audio_parse) = if url.starts_with("srt://") {
+            ("mpegtsmux alignment=7", "srtsink", "uri", "opusenc", "")
+        } else if url.starts_with("rtmp://") || url.starts_with("rtmps://") {
+            (
+                "flvmux streamable=true",
+                "rtmpsink",
+                "location",
+                "avenc_aac",
+                "! aacparse",
+            )
+        } else {
+            bail!(
+                "unsupported push URL '{}': only srt:// and rtmp:// are supported",
+                url
+            );
+        };
+
+        let mut desc = format!(
+            "{} name=push_mux ! {} name=push_sink intervideosrc channel={} ! videoconvert ! x264enc tune=zerolatency speed-preset=veryfast bitrate={} key-int-max=60 ! video/x-h264,profile=main ! h264parse ! queue ! push_mux. ",
+            mux, sink, PUSH_VIDEO_CHANNEL, conf.video_bitrate
+        );
+        if audio {
+            desc.push_str(&format!(
+                "interaudiosrc channel={} ! audioconvert ! audioresample ! audio/x-raw,rate=48000 ! {} bitrate={} {} ! queue ! push_mux.",
+                PUSH_AUDIO_CHANNEL,
+                audio_enc,
+                conf.audio_bitrate * 1000,
+                audio_parse
+            ));
+        }
+
+        let pipeline: gstreamer::Pipeline = gstreamer::parse_launch(&desc)?
+            .downcast()
+            .map_err(|_| anyhow!("broken push pipeline"))?;
+
+        let push_sink = pipeline
+            .by_name("push_sink")
+            .ok_or_else(|| anyhow!("missing push sink"))?;
+        push_sink.set_property(sink_property, url);
+
+        let status = Arc::new(PushStatus::default());
+
+        // count what gets sent to the server, to compute the bitrate
+        let counter = status.clone();
+        push_sink
+            .static_pad("sink")
+            .ok_or_else(|| anyhow!("missing push sink pad"))?
+            .add_probe(gstreamer::PadProbeType::BUFFER, move |_, info| {
+                if let Some(gstreamer::PadProbeData::Buffer(b)) = &info.data {
+                    counter.bytes.fetch_add(b.size() as u64, Ordering::Relaxed);
+                }
+                gstreamer::PadProbeReturn::Ok
+            });
+
+        let rate = status.clone();
+        let bitrate_task = tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(1));
+            loop {
+                interval.tick().await;
+                let bytes = rate.bytes.swap(0, Ordering::Relaxed);
+                rate.kbps.store(bytes * 8 / 1000, Ordering::Relaxed);
+            }
+        });
+
+        let bus = pipeline
+            .bus()
+            .ok_or_else(|| anyhow!("No bus for push pipeline"))?;
+        let state = status.clone();
+        let p = pipeline.clone();
+        let bus_task = tokio::spawn(async move {
+            let mut messages = bus.stream();
+            while let Some(msg) = messages.next().await {
+                use gstreamer::MessageView;
+
+                match msg.view() {
+                    MessageView::StateChanged(s)
+                        if s.src().as_ref() == Some(p.upcast_ref())
+                            && s.current() == gstreamer::State::Playing =>
+                    {
+                        state.state.store(LIVE, Ordering::Relaxed);
+                    }
+                    MessageView::Error(err) => {
+                        warn!("push output failed: {} ({:?})", err.error(), err.debug());
+                        state.state.store(FAILED, Ordering::Relaxed);
+
+                        // try again later, the server might come back
+                        let _ = p.set_state(gstreamer::State::Null);
+                        tokio::time::sleep(Duration::from_secs(5)).await;
+                        state.state.store(CONNECTING, Ordering::Relaxed);
+                        if let Err(e) = p.set_state(gstreamer::State::Playing) {
+                            debug!("failed to restart push output: {}", e);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        });
+
+        pipeline.set_state(gstreamer::State::Playing)?;
+        info!("pushing stream to {}", url);
+
+        Ok(Push {
+            pipeline,
+            status,
+            tasks: vec![bitrate_task, bus_task],
+        })
+    }
+
+    pub fn status(&self) -> Arc<PushStatus> {
+        self.status.clone()
+    }
+}
+
+impl Drop for Push {
+    fn drop(&mut self) {
+        for t in &self.tasks {
+            t.abort();
+        }
+        if let Err(e) = self.pipeline.set_state(gstreamer::State::Null) {
+            error!("{}", e);
+        }
+    }
+}
@@ -0,0 +1,68 @@
+use anyhow::{anyhow, Result};
+use gstreamer_rtsp_server::glib;
+use gstreamer_rtsp_server::prelude::*;
+use gstreamer_rtsp_server::{RTSPMediaFactory, RTSPServer};
+use log::*;
+
+use crate::config::RtspConfig;
+use crate::{RTSP_AUDIO_CHANNEL, RTSP_VIDEO_CHANNEL};
+
+pub struct RtspServer {
+    main_loop: glib::MainLoop,
+    source: Option<glib::SourceId>,
+}
+
+impl RtspServer {
+    pub fn start(conf: &RtspConfig, audio: bool) -> Result<RtspServer> {
+        let server = RTSPServer::new();
+        server.set_address(&conf.address);
+        server.set_service(&conf.port.to_string());
+
+        let mut launch = format!(
+            "( intervideosrc channel={} ! videoconvert ! x264enc tune=zerolatency speed-preset=ultrafast ! rtph264pay name=pay0 pt=96 ",
+            RTSP_VIDEO_CHANNEL
+        );
+        if audio {
+            launch.push_str(&format!(
+                "interaudiosrc channel={} ! audioconvert ! audioresample ! opusenc ! rtpopuspay name=pay1 pt=97 ",
+                RTSP_AUDIO_CHANNEL
+            ));
+        }
+        launch.push(')');
+
+        let factory = RTSPMediaFactory::new();
+        factory.set_launch(&launch);
+        // all clients share the same encoders
+        factory.set_shared(true);
+
+        server
+            .mount_points()
+            .ok_or_else(|| anyhow!("missing RTSP mount points"))?
+            .add_factory(&conf.mount, &factory);
+
+        // the server runs on the default glib main context, so it needs its own main loop
+        let source = server.attach(None)?;
+        let main_loop = glib::MainLoop::new(None, false);
+        let l = main_loop.clone();
+        std::thread::spawn(move || l.run());
+
+        info!(
+            "serving RTSP stream on rtsp://{}:{}{}",
+            conf.address, conf.port, conf.mount
+        );
+
+        Ok(RtspServer {
+            main_loop,
+            source: Some(source),
+        })
+    }
+}
+
+impl Drop for RtspServer {
+    fn drop(&mut self) {
+        if let Some(source) = self.source.take() {
+            source.remove();
+        }
+        self.main_loop.quit();
+    }
+}
@@ -0,0 +1,346 @@
+use std::io::Write;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+use log::*;
+use nix::sys::signal::{kill, Signal};
+use nix::unistd::Pid;
+use serde::{Deserialize, Serialize};
+
+use crate::pulse::{Module, Pulse};
+
+/// Everything dcamctl changed on the system and needs to undo on exit.
+///
+/// This is kept on disk while dcamctl runs (one file per process), so that it can still be undone
+/// after a crash.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct State {
+    pub pid: u32,
+    #[serde(default)]
+    pub adb_forward: Option<AdbForward>,
+    /// Audio modules loaded by dcamctl, in load order.
+    #[serde(default)]
+    pub modules: Vec<u32>,
+    #[serde(default)]
+    pub default_source: Option<String>,
+    #[serde(default)]
+    pub default_sink: Option<String>,
+    /// The pipewire process running the native echo cancellation.
+    #[serde(default)]
+    pub echo_cancel_pid: Option<u32>,
+    /// The configuration file given to the echo cancellation process.
+    #[serde(default)]
+    pub echo_cancel_conf: Option<PathBuf>,
+    /// The name of the virtual sink, to recognize the modules loaded by dcamctl.
+    #[serde(default)]
+    pub sink_name: Option<String>,
+    /// The name of the echo cancelled source, to recognize the modules loaded by dcamctl.
+    #[serde(default)]
+    pub ec_source_name: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AdbForward {
+    pub port: u16,
+    pub serial: Option<String>,
+}
+
+impl State {
+    fn is_empty(&self) -> bool {
+        self.adb_forward.is_none()
+            && self.modules.is_empty()
+            && self.default_source.is_none()
+            && self.default_sink.is_none()
+            && self.echo_cancel_pid.is_none()
+            && self.echo_cancel_conf.is_none()
+    }
+
+    /// Returns true if the module was loaded by dcamctl, and not by something else that got the
+    /// same index after a restart of the sound server.
+    fn owns_module(&self, module: &Module) -> bool {
+        let has_arg = |key: &str, value: &Option<String>| match value {
+            Some(v) => module
+                .argument
+                .split_whitespace()
+                .any(|a| a == format!("{}={}", key, v)),
+            None => false,
+        };
+        match module.name.as_str() {
+            "module-null-sink" => has_arg("sink_name", &self.sink_name),
+            "module-echo-cancel" => has_arg("source_name", &self.ec_source_name),
+            _ => false,
+        }
+    }
+
+    /// Returns true if the dcamctl process that wrote this state is still running.
+    fn owner_running(&self) -> bool {
+        self.pid != std::process::id()
+            && std::fs::read_to_string(format!("/proc/{}/comm", self.pid))
+                .map(|c| c.trim() == "dcamctl")
+                .unwrap_or(false)
+    }
+}
+
+fn state_path() -> Result<PathBuf> {
+    Ok(crate::runtime_dir()?.join(format!("state-{}.json", std::process::id())))
+}
+
+fn load(path: &Path) -> Result<Option<State>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let content = std::fs::read(path)
+        .with_context(|| format!("failed to read state file {}", path.display()))?;
+    let state = serde_json::from_slice(&content)
+        .with_context(|| format!("failed to parse state file {}", path.display()))?;
+
+    Ok(Some(state))
+}
+
+fn save(path: &Path, state: &State) -> Result<()> {
+    if state.is_empty() {
+        if path.exists() {
+            std::fs::remove_file(path)
+                .with_context(|| format!("failed to remove state file {}", path.display()))?;
+        }
+        return Ok(());
+    }
+
+    // write to a temporary file first, so that a crash never leaves a truncated state behind
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, serde_json::to_vec_pretty(state)?)
+        .with_context(|| format!("failed to write state file {}", tmp.display()))?;
+    std::fs::rename(&tmp, path)
+        .with_context(|| format!("failed to write state file {}", path.display()))?;
+
+    Ok(())
+}
+
+/// Records a change to the system in the state file.
+///
+/// Failing to do so isn't fatal: only the cleanup after a crash is affected.
+pub fn update<F: FnOnce(&mut State)>(f: F) {
+    let res = state_path().and_then(|path| {
+        let mut s = load(&path)?.unwrap_or_default();
+        s.pid = std::process::id();
+        f(&mut s);
+        save(&path, &s)
+    });
+    if let Err(e) = res {
+        warn!("could not update state file: {:#}", e);
+    }
+}
+
+/// Finds the states left behind by dcamctl processes that aren't running anymore.
+fn leftovers() -> Result<Vec<(PathBuf, State)>> {
+    let dir = crate::runtime_dir()?;
+    let mut states = Vec::new();
+    for entry in
+        std::fs::read_dir(&dir).with_context(|| format!("failed to list {}", dir.display()))?
+    {
+        let path = entry?.path();
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if !(name.starts_with("state-") && name.ends_with(".json")) {
+            continue;
+        }
+
+        match load(&path) {
+            Ok(Some(state)) if state.owner_running() => {
+                debug!("dcamctl process {} is still running", state.pid)
+            }
+            Ok(Some(state)) if path == state_path()? => {
+                // a previous run had the same pid: move its state out of the way, so that it
+                // doesn't get mixed with the state of this run
+                let moved = dir.join(format!(
+                    "state-{}-{}.json",
+                    state.pid,
+                    SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_nanos()
+                ));
+                std::fs::rename(&path, &moved)
+                    .with_context(|| format!("failed to move state file {}", path.display()))?;
+                states.push((moved, state));
+            }
+            Ok(Some(state)) => states.push((path, state)),
+            Ok(None) => {}
+            Err(e) => warn!("ignoring state of previous run: {:#}", e),
+        }
+    }
+
+    Ok(states)
+}
+
+/// Checks for leftovers of previous dcamctl runs that didn't exit cleanly, and offers to clean
+/// them up.
+pub fn check_leftovers() -> Result<()> {
+    let states = match leftovers() {
+        Ok(s) if s.is_empty() => return Ok(()),
+        Ok(s) => s,
+        Err(e) => {
+            warn!("could not look for leftovers of previous runs: {:#}", e);
+            // never take over the state of a previous run that had the same pid
+            let path = state_path()?;
+            if path.exists() {
+                bail!(
+                    "a state file of a previous run is in the way: {}",
+                    path.display()
+                );
+            }
+            return Ok(());
+        }
+    };
+
+    for (_, state) in &states {
+        let pid = state.pid;
+        show!(
+            Warn,
+            "A previous dcamctl run (pid {}) didn't exit cleanly and left audio or adb settings behind.",
+            pid
+        );
+    }
+    if termion::is_tty(&std::io::stdin()) {
+        print!("Clean them up now? [Y/n] ");
+        std::io::stdout().flush()?;
+        let mut answer = String::new();
+        std::io::stdin().read_line(&mut answer)?;
+        if matches!(answer.trim(), "" | "y" | "Y" | "yes") {
+            let mut restored = true;
+            for (path, state) in states {
+                if let Err(e) = restore(&path, state) {
+                    warn!("{:#}", e);
+                    restored = false;
+                }
+            }
+            if restored {
+                return Ok(());
+            }
+        }
+    }
+    show!(Warn, "Run 'dcamctl cleanup' to restore them.");
+
+    Ok(())
+}
+
+/// Restores everything recorded in the state files of previous runs.
+pub fn cleanup() -> Result<()> {
+    let states = leftovers()?;
+    if states.is_empty() {
+        show!(Warn, "Nothing to clean up.");
+    }
+
+    let mut failed = 0;
+    for (path, state) in states {
+        if let Err(e) = restore(&path, state) {
+            warn!("{:#}", e);
+            failed += 1;
+        }
+    }
+    if failed > 0 {
+        bail!("{} previous run(s) could not be fully cleaned up", failed);
+    }
+
+    Ok(())
+}
+
+/// Returns true if the process is still the pipewire instance started with the configuration.
+fn is_echo_cancel_process(pid: u32, conf: Option<&Path>) -> bool {
+    let comm = std::fs::read_to_string(format!("/proc/{}/comm", pid)).unwrap_or_default();
+    let cmdline = std::fs::read(format!("/proc/{}/cmdline", pid)).unwrap_or_default();
+    let conf = match conf {
+        Some(c) => c.as_os_str().as_bytes(),
+        None => return false,
+    };
+    comm.trim() == "pipewire" && cmdline.split(|b| *b == 0).any(|arg| arg == conf)
+}
+
+fn restore(path: &Path, mut state: State) -> Result<()> {
+    if let Some(pid) = state.echo_cancel_pid.take() {
+        // the process might be long gone already, and its pid reused
+        if is_echo_cancel_process(pid, state.echo_cancel_conf.as_deref()) {
+            debug!("stopping echo cancellation process {}", pid);
+            let _ = kill(Pid::from_raw(pid as i32), Signal::SIGTERM);
+        } else {
+            debug!("echo cancellation process {} isn't running anymore", pid);
+        }
+    }
+    if let Some(conf) = state.echo_cancel_conf.take() {
+        if let Err(e) = std::fs::remove_file(&conf) {
+            debug!("could not remove {}: {}", conf.display(), e);
+        }
+    }
+
+    // every step is best-effort: what fails is kept in the state, to be retried later
+    if !state.modules.is_empty() || state.default_source.is_some() || state.default_sink.is_some() {
+        match Pulse::connect() {
+            Ok(mut pulse) => restore_audio(&mut pulse, &mut state),
+            Err(e) => warn!("could not restore audio settings: {:#}", e),
+        }
+    }
+
+    if let Some(forward) = state.adb_forward.take() {
+        let port = forward.port;
+        crate::remove_adb_forward(port, forward.serial.as_deref());
+        show!(Warn, "Removed adb forwarding of port {}", port);
+    }
+
+    save(path, &state)?;
+    if !state.is_empty() {
+        bail!(
+            "some settings left by dcamctl process {} could not be restored",
+            state.pid
+        );
+    }
+
+    Ok(())
+}
+
+fn restore_audio(pulse: &mut Pulse, state: &mut State) {
+    if let Some(source) = state.default_source.take() {
+        match pulse.set_default_source(&source) {
+            Ok(()) => show!(Warn, "Restored default audio source '{}'", source),
+            Err(e) => {
+                warn!("failed to set default source back to {}: {:#}", source, e);
+                state.default_source = Some(source);
+            }
+        }
+    }
+    if let Some(sink) = state.default_sink.take() {
+        match pulse.set_default_sink(&sink) {
+            Ok(()) => show!(Warn, "Restored default audio sink '{}'", sink),
+            Err(e) => {
+                warn!("failed to set default sink back to {}: {:#}", sink, e);
+                state.default_sink = Some(sink);
+            }
+        }
+    }
+
+    let loaded = match pulse.list_modules() {
+        Ok(l) => l,
+        Err(e) => {
+            warn!("could not list audio modules: {:#}", e);
+            return;
+        }
+    };
+    let mut failed = Vec::new();
+    while let Some(index) = state.modules.pop() {
+        // the module might have been unloaded before the crash, or with the sound server, in
+        // which case its index might now belong to another module
+        match loaded.iter().find(|m| m.index == index) {
+            Some(module) if state.owns_module(module) => {
+                if let Err(e) = pulse.unload_module(index) {
+                    warn!("could not unload audio module {}: {:#}", index, e);
+                    failed.insert(0, index);
+                } else {
+                    show!(Warn, "Unloaded audio module {}", index);
+                }
+            }
+            _ => debug!("audio module {} isn't dcamctl's anymore", index),
+        }
+    }
+    state.modules = failed;
+}